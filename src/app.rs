@@ -32,6 +32,7 @@ where
 impl Application {
     pub fn run(&self) {
         match run_command_from_parser(&self.parser) {
+            Ok(s) if s.is_empty() => {}
             Ok(s) => {
                 println!("{}", s)
             }
@@ -43,7 +44,9 @@ impl Application {
 
     pub fn try_run(&self) -> Result<(), Error> {
         let output = run_command_from_parser(&self.parser)?;
-        println!("{}", output);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
         Ok(())
     }
 }
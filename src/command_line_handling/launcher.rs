@@ -1,6 +1,9 @@
 use super::parser::CommandLineArgumentsParser;
-use crate::util::vcs_state::find_repos_root;
-use std::io::Error;
+use crate::util::diff::{ConflictStyle, Favor};
+use crate::util::merge::MergeKind;
+use crate::util::rename::{parse_rename_threshold, DEFAULT_RENAME_THRESHOLD};
+use crate::util::vcs_state::{find_repos_root, relative_to_repos_root};
+use std::io::{stdout, Error, ErrorKind};
 use std::path::Path;
 
 /// Runs a vcs command corresponding to the data from parser
@@ -10,16 +13,273 @@ pub fn run_command_from_parser(parser: &CommandLineArgumentsParser) -> Result<St
     use super::parser::VcsCommands::*;
     use crate::vcs_commands::*;
 
+    let rename_threshold = match &parser.rename_threshold {
+        Some(x) => parse_rename_threshold(x)?,
+        None => Some(DEFAULT_RENAME_THRESHOLD),
+    };
     match &parser.command {
         Init { path } => init::run(Path::new(path)),
-        Status => status::run(&find_repos_root()?),
-        Commit { message } => commit::run(&find_repos_root()?, message),
+        Status => status::run(&find_repos_root()?, rename_threshold),
+        Commit {
+            message,
+            amend: true,
+            ..
+        } => commit::amend(&find_repos_root()?, message, rename_threshold),
+        Commit {
+            message,
+            branch: Some(name),
+            ..
+        } => {
+            // unwrap: clap requires the message without --amend
+            commit::on_new_branch(
+                &find_repos_root()?,
+                message.as_ref().unwrap(),
+                name,
+                rename_threshold,
+            )
+        }
+        Commit { message, .. } => {
+            // unwrap: clap requires the message without --amend
+            commit::run(
+                &find_repos_root()?,
+                message.as_ref().unwrap(),
+                rename_threshold,
+            )
+        }
         Jump {
             branch_name,
             commit_hash,
         } => jump::run(&find_repos_root()?, branch_name, commit_hash),
-        NewBranch { name } => new_branch::run(&find_repos_root()?, name),
-        Merge { branch } => merge::run(&find_repos_root()?, branch),
-        Log => log::run(&find_repos_root()?),
+        NewBranch { name, from } => new_branch::run(&find_repos_root()?, name, from),
+        Merge {
+            continue_merge: true,
+            ..
+        } => merge::continue_merge(&find_repos_root()?, MergeKind::Merge, rename_threshold),
+        Merge { abort: true, .. } => merge::abort(&find_repos_root()?, MergeKind::Merge),
+        Merge {
+            branches,
+            branch,
+            diff3,
+            ff_only,
+            no_ff,
+            squash,
+            strategy,
+            strategy_option,
+            dry_run,
+            ..
+        } => {
+            let options = merge::MergeOptions {
+                conflict_style: if *diff3 {
+                    ConflictStyle::Diff3
+                } else {
+                    ConflictStyle::Merge
+                },
+                fast_forward: if *ff_only {
+                    merge::FastForward::Only
+                } else if *no_ff {
+                    merge::FastForward::Never
+                } else {
+                    merge::FastForward::Allow
+                },
+                favor: match strategy_option.as_deref() {
+                    Some("ours") => Favor::Ours,
+                    Some("theirs") => Favor::Theirs,
+                    _ => Favor::None,
+                },
+                strategy: if strategy == "ours" {
+                    merge::Strategy::Ours
+                } else {
+                    merge::Strategy::Recursive
+                },
+                squash: *squash,
+                dry_run: *dry_run,
+                rename_threshold,
+            };
+            // clap requires a branch without --continue or --abort
+            let branches: Vec<String> = branch.iter().chain(branches.iter()).cloned().collect();
+            if branches.len() == 1 {
+                merge::run(&find_repos_root()?, &branches[0], &options)
+            } else {
+                merge::octopus(&find_repos_root()?, &branches, &options)
+            }
+        }
+        Bisect { command } => {
+            use super::parser::BisectCommands;
+            let repos_root = find_repos_root()?;
+            match command {
+                BisectCommands::Start { bad, good } => bisect::start(&repos_root, bad, good),
+                BisectCommands::Good { revision } => bisect::good(&repos_root, revision),
+                BisectCommands::Bad { revision } => bisect::bad(&repos_root, revision),
+                BisectCommands::Skip { revision } => bisect::skip(&repos_root, revision),
+                BisectCommands::Reset => bisect::reset(&repos_root),
+                BisectCommands::Run { command } => bisect::run(&repos_root, command),
+            }
+        }
+        Undo => op::undo(&find_repos_root()?),
+        Redo => op::redo(&find_repos_root()?),
+        Op { command } => {
+            use super::parser::OpCommands;
+            match command {
+                OpCommands::Log => op::log(&find_repos_root()?),
+            }
+        }
+        Reflog { name, expire: true } => reflog::expire(&find_repos_root()?, name),
+        Reflog { name, .. } => reflog::run(&find_repos_root()?, name),
+        Reset {
+            revision,
+            soft,
+            mixed: _,
+            hard,
+        } => {
+            let mode = if *soft {
+                reset::ResetMode::Soft
+            } else if *hard {
+                reset::ResetMode::Hard
+            } else {
+                reset::ResetMode::Mixed
+            };
+            reset::run(&find_repos_root()?, revision, mode)
+        }
+        CherryPick {
+            continue_pick: true,
+            ..
+        } => merge::continue_merge(&find_repos_root()?, MergeKind::CherryPick, rename_threshold),
+        Revert {
+            continue_revert: true,
+            ..
+        } => merge::continue_merge(&find_repos_root()?, MergeKind::Revert, rename_threshold),
+        CherryPick { abort: true, .. } => merge::abort(&find_repos_root()?, MergeKind::CherryPick),
+        Revert { abort: true, .. } => merge::abort(&find_repos_root()?, MergeKind::Revert),
+        CherryPick { revisions, .. } => {
+            cherry_pick::run(&find_repos_root()?, revisions, rename_threshold)
+        }
+        Revert { revisions, .. } => revert::run(&find_repos_root()?, revisions, rename_threshold),
+        Rebase {
+            continue_rebase: true,
+            ..
+        } => rebase::continue_rebase(&find_repos_root()?, rename_threshold),
+        Rebase { skip: true, .. } => rebase::skip(&find_repos_root()?, rename_threshold),
+        Rebase { abort: true, .. } => rebase::abort(&find_repos_root()?),
+        Rebase {
+            upstream,
+            interactive,
+            todo,
+            ..
+        } => {
+            // unwrap: clap requires the upstream without --continue, --skip or --abort
+            let upstream = upstream.as_ref().unwrap();
+            if *interactive {
+                rebase::interactive(&find_repos_root()?, upstream, todo, rename_threshold)
+            } else {
+                rebase::run(&find_repos_root()?, upstream, rename_threshold)
+            }
+        }
+        Log { first_parent } => log::run(&find_repos_root()?, *first_parent),
+        Restore {
+            paths,
+            source,
+            delete_added,
+        } => {
+            let repos_root = find_repos_root()?;
+            let paths = paths
+                .iter()
+                .map(|x| relative_to_repos_root(&repos_root, x))
+                .collect::<Result<Vec<String>, Error>>()?;
+            restore::run(&repos_root, &paths, source, *delete_added)
+        }
+        Checkout {
+            paths,
+            ours: _,
+            theirs,
+        } => {
+            let repos_root = find_repos_root()?;
+            let paths = paths
+                .iter()
+                .map(|x| relative_to_repos_root(&repos_root, x))
+                .collect::<Result<Vec<String>, Error>>()?;
+            checkout::run(&repos_root, &paths, *theirs)
+        }
+        Resolve { paths } => {
+            let repos_root = find_repos_root()?;
+            let paths = paths
+                .iter()
+                .map(|x| relative_to_repos_root(&repos_root, x))
+                .collect::<Result<Vec<String>, Error>>()?;
+            resolve::run(&repos_root, &paths)
+        }
+        Stash { command } => {
+            use super::parser::StashCommands;
+            let repos_root = find_repos_root()?;
+            match command {
+                None => stash::push(&repos_root, &None, rename_threshold),
+                Some(StashCommands::Push { message }) => {
+                    stash::push(&repos_root, message, rename_threshold)
+                }
+                Some(StashCommands::List) => stash::list(&repos_root),
+                Some(StashCommands::Show { index }) => stash::show(&repos_root, *index),
+                Some(StashCommands::Apply { index }) => stash::apply(&repos_root, *index, false),
+                Some(StashCommands::Pop { index }) => stash::apply(&repos_root, *index, true),
+                Some(StashCommands::Drop { index }) => stash::drop(&repos_root, *index),
+            }
+        }
+        Branch {
+            names,
+            delete,
+            force_delete,
+            rename,
+            describe,
+        } => {
+            let repos_root = find_repos_root()?;
+            let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+            match (names.as_slice(), describe) {
+                ([name], _) if *delete || *force_delete => {
+                    branch::delete(&repos_root, name, *force_delete)
+                }
+                ([new_name], _) if *rename => branch::rename(&repos_root, None, new_name),
+                ([old_name, new_name], _) if *rename => {
+                    branch::rename(&repos_root, Some(old_name), new_name)
+                }
+                ([], Some(text)) => branch::describe(&repos_root, None, text),
+                ([name], Some(text)) => branch::describe(&repos_root, Some(name), text),
+                ([], None) if !*delete && !*force_delete && !*rename => branch::list(&repos_root),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "error: wrong number of branch names. Use new-branch to create a branch",
+                )),
+            }
+        }
+        Tag {
+            name,
+            revision,
+            message,
+            delete,
+            show,
+        } => {
+            let repos_root = find_repos_root()?;
+            match name {
+                None => tag::list(&repos_root),
+                Some(name) if *delete => tag::delete(&repos_root, name),
+                Some(name) if *show => tag::show(&repos_root, name),
+                Some(name) => tag::create(&repos_root, name, revision, message),
+            }
+        }
+        Gc => gc::run(&find_repos_root()?),
+        Blame {
+            path,
+            revision,
+            lines,
+            incremental,
+        } => {
+            let repos_root = find_repos_root()?;
+            let path = relative_to_repos_root(&repos_root, path)?;
+            if *incremental {
+                let mut out = stdout().lock();
+                blame::incremental(&repos_root, &path, revision, lines, &mut out)?;
+                // the blocks are printed already
+                Ok(String::new())
+            } else {
+                blame::run(&repos_root, &path, revision, lines)
+            }
+        }
     }
 }
@@ -1,4 +1,6 @@
+use crate::util::rename::parse_rename_threshold;
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 /// Simple Version Control System
 #[derive(Parser)]
@@ -6,6 +8,16 @@ use clap::{Parser, Subcommand};
 pub struct CommandLineArgumentsParser {
     #[command(subcommand)]
     pub command: VcsCommands,
+
+    /// Minimal similarity in percents for status, commit, merge, rebase and cherry-pick
+    /// to take a file as renamed or copied, "off" disables the detection. 50% by default
+    #[arg(
+        long,
+        global(true),
+        value_name("PERCENT"),
+        value_parser(parse_threshold)
+    )]
+    pub rename_threshold: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -22,8 +34,16 @@ pub enum VcsCommands {
 
     /// Create a new commit. The current commit must be the last one in the branch
     Commit {
-        #[arg(short, long)]
-        message: String,
+        #[arg(short, long, required_unless_present("amend"))]
+        message: Option<String>,
+
+        /// Replace the last commit of the branch keeping its parents, and its message unless provided
+        #[arg(long)]
+        amend: bool,
+
+        /// Start a new branch from the current commit and commit there
+        #[arg(short, long, value_name("BRANCH_NAME"), conflicts_with("amend"))]
+        branch: Option<String>,
     },
 
     /// Switch branch or restore working tree files
@@ -43,19 +63,437 @@ pub enum VcsCommands {
         commit_hash: Option<String>,
     },
 
-    /// Create a new branch from the current commit in master
+    /// Create a new branch from the current commit or the given revision and switch to it
     #[command(visible_alias("new_branch"))]
     NewBranch {
         #[arg(short, long, value_name("BRANCH_NAME"))]
         name: String,
+
+        /// Start the branch from this revision instead of the current commit
+        #[arg(short, long, value_name("REVISION"))]
+        from: Option<String>,
     },
 
-    /// Merge the branch into master. The current commit must be the last one in master
+    /// Merge branches into the current one. The current commit must be the last one in the branch
     Merge {
+        /// Branches to merge, several ones are merged at once into a commit with all of them as parents
+        #[arg(
+            value_name("BRANCH_NAME"),
+            required_unless_present_any(["branch", "continue_merge", "abort"])
+        )]
+        branches: Vec<String>,
+
         #[arg(short, long, value_name("BRANCH_NAME"))]
-        branch: String,
+        branch: Option<String>,
+
+        /// Show the base version in conflicts as well
+        #[arg(long)]
+        diff3: bool,
+
+        /// Merge strategy: "recursive" or "ours" keeping the current tree as it is
+        #[arg(
+            short,
+            long,
+            value_name("STRATEGY"),
+            value_parser(["recursive", "ours"]),
+            default_value("recursive")
+        )]
+        strategy: String,
+
+        /// Resolve conflicting hunks in favor of one side: "ours" or "theirs"
+        #[arg(
+            short('X'),
+            long,
+            value_name("OPTION"),
+            value_parser(["ours", "theirs"])
+        )]
+        strategy_option: Option<String>,
+
+        /// Refuse to merge unless the current branch can be fast-forwarded
+        #[arg(long, conflicts_with("no_ff"))]
+        ff_only: bool,
+
+        /// Create a merge commit even if a fast-forward is possible
+        #[arg(long)]
+        no_ff: bool,
+
+        /// Commit the combined changes of the branch as an ordinary commit on the current branch
+        #[arg(long, conflicts_with_all(["no_ff", "ff_only"]))]
+        squash: bool,
+
+        /// Report what the merge would do without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Create the merge commit once the conflicts are resolved
+        #[arg(long("continue"), conflicts_with_all(["branches", "branch", "abort"]))]
+        continue_merge: bool,
+
+        /// Drop the merge stopped because of conflicts
+        #[arg(long, conflicts_with_all(["branches", "branch"]))]
+        abort: bool,
+    },
+
+    /// Find the commit that introduced a regression by binary search
+    Bisect {
+        #[command(subcommand)]
+        command: BisectCommands,
+    },
+
+    /// Return HEAD and branches to where they were before the last operation
+    Undo,
+
+    /// Make the last undone operation again
+    Redo,
+
+    /// Inspect the operations made by the commands
+    Op {
+        #[command(subcommand)]
+        command: OpCommands,
+    },
+
+    /// Show movements of HEAD or a branch, an entry can be referred to as <REF>@{N}
+    Reflog {
+        /// HEAD by default
+        #[arg(value_name("REF"))]
+        name: Option<String>,
+
+        /// Drop the entries, commits kept only by them are removed by the next gc
+        #[arg(long)]
+        expire: bool,
+    },
+
+    /// Move the current branch and HEAD to another commit
+    Reset {
+        #[arg(value_name("REVISION"))]
+        revision: String,
+
+        /// Keep the working tree
+        #[arg(long, conflicts_with_all(["mixed", "hard"]))]
+        soft: bool,
+
+        /// Keep the working tree, the same as --soft since there is no staging area. The default
+        #[arg(long, conflicts_with("hard"))]
+        mixed: bool,
+
+        /// Make the working tree match the commit dropping all local changes
+        #[arg(long)]
+        hard: bool,
+    },
+
+    /// Apply the changes introduced by commits onto the current commit as new commits
+    CherryPick {
+        #[arg(
+            value_name("REVISION"),
+            required_unless_present_any(["continue_pick", "abort"])
+        )]
+        revisions: Vec<String>,
+
+        /// Commit the resolved conflicts
+        #[arg(long("continue"), conflicts_with_all(["revisions", "abort"]))]
+        continue_pick: bool,
+
+        /// Drop the changes of the commit that stopped because of conflicts
+        #[arg(long, conflicts_with("revisions"))]
+        abort: bool,
+    },
+
+    /// Create commits undoing the changes introduced by commits
+    Revert {
+        #[arg(
+            value_name("REVISION"),
+            required_unless_present_any(["continue_revert", "abort"])
+        )]
+        revisions: Vec<String>,
+
+        /// Commit the resolved conflicts
+        #[arg(long("continue"), conflicts_with_all(["revisions", "abort"]))]
+        continue_revert: bool,
+
+        /// Drop the changes of the commit that stopped because of conflicts
+        #[arg(long, conflicts_with("revisions"))]
+        abort: bool,
+    },
+
+    /// Replay commits of the current branch on top of another commit
+    Rebase {
+        /// The revision to rebase the current branch onto
+        #[arg(
+            value_name("UPSTREAM"),
+            required_unless_present_any(["continue_rebase", "skip", "abort"])
+        )]
+        upstream: Option<String>,
+
+        /// Edit the list of commits to replay: reorder, reword, squash, fixup, drop or edit them
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Read the todo list from the file instead of opening $EDITOR
+        #[arg(long, value_name("FILE"), requires("interactive"))]
+        todo: Option<PathBuf>,
+
+        /// Commit the resolved conflicts and replay the rest of the commits
+        #[arg(long("continue"), conflicts_with_all(["upstream", "skip", "abort"]))]
+        continue_rebase: bool,
+
+        /// Drop the commit whose replay stopped and replay the rest
+        #[arg(long, conflicts_with_all(["upstream", "abort"]))]
+        skip: bool,
+
+        /// Return the branch to the commit it was at before the rebase
+        #[arg(long, conflicts_with("upstream"))]
+        abort: bool,
     },
 
     /// List commits that are reachable by following parent links from current commit
+    Log {
+        /// Follow only the first parent of merge commits
+        #[arg(long)]
+        first_parent: bool,
+    },
+
+    /// Take one whole side of conflicting files during a merge
+    Checkout {
+        /// Conflicting files or directories. All conflicting files by default
+        #[arg(value_name("PATH"))]
+        paths: Vec<String>,
+
+        /// Take the version of the current commit
+        #[arg(long, required_unless_present("theirs"), conflicts_with("theirs"))]
+        ours: bool,
+
+        /// Take the version of the merged commit
+        #[arg(long)]
+        theirs: bool,
+    },
+
+    /// Mark conflicting files of a merge or rebase as resolved
+    Resolve {
+        /// Conflicting files or directories
+        #[arg(value_name("PATH"), required(true))]
+        paths: Vec<String>,
+    },
+
+    /// Restore working tree files from a commit without moving HEAD
+    Restore {
+        /// Files or directories to restore. The whole working tree by default
+        #[arg(value_name("PATH"))]
+        paths: Vec<String>,
+
+        /// Take the files from the given revision instead of the current commit
+        #[arg(short, long, value_name("REVISION"))]
+        source: Option<String>,
+
+        /// Delete files that are absent in the source commit
+        #[arg(short, long)]
+        delete_added: bool,
+    },
+
+    /// Put local changes aside and restore the current commit
+    Stash {
+        #[command(subcommand)]
+        command: Option<StashCommands>,
+    },
+
+    /// List, delete, rename or describe branches
+    Branch {
+        /// Branches the option applies to, the current one by default
+        #[arg(value_name("BRANCH_NAME"), num_args(0..=2))]
+        names: Vec<String>,
+
+        /// Delete a branch that is fully merged into the current commit
+        #[arg(short, long, group("action"))]
+        delete: bool,
+
+        /// Delete a branch even if it is not merged
+        #[arg(short('D'), group("action"))]
+        force_delete: bool,
+
+        /// Rename a branch: [OLD_NAME] NEW_NAME
+        #[arg(short('m'), long("move"), group("action"))]
+        rename: bool,
+
+        /// Set the branch description, an empty one removes it
+        #[arg(long, value_name("TEXT"), group("action"))]
+        describe: Option<String>,
+    },
+
+    /// Create, list or delete tags
+    Tag {
+        /// Tag to create. All tags are listed if omitted
+        #[arg(value_name("TAG_NAME"))]
+        name: Option<String>,
+
+        /// Revision to tag, the current commit by default
+        #[arg(value_name("REVISION"), conflicts_with_all(["delete", "show"]))]
+        revision: Option<String>,
+
+        /// Create an annotated tag with the given message
+        #[arg(short, long, conflicts_with_all(["delete", "show"]))]
+        message: Option<String>,
+
+        /// Delete the tag
+        #[arg(short, long, requires("name"), conflicts_with("show"))]
+        delete: bool,
+
+        /// Show the tag and its annotation
+        #[arg(short, long, requires("name"))]
+        show: bool,
+    },
+
+    /// Remove commits that are unreachable from branches, tags, HEAD and stash
+    Gc,
+
+    /// Show the commit that introduced each line of the file
+    Blame {
+        #[arg(value_name("PATH"))]
+        path: String,
+
+        /// Blame the file as of the given revision instead of the current commit
+        #[arg(value_name("REVISION"))]
+        revision: Option<String>,
+
+        /// Annotate only the lines from START to END (1-based, inclusive)
+        #[arg(short('L'), value_name("START,END"), value_parser(parse_line_range))]
+        lines: Option<(usize, usize)>,
+
+        /// Print blocks of lines in machine-readable form as they are found
+        #[arg(long)]
+        incremental: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BisectCommands {
+    /// Start the search, optionally with a bad revision followed by good ones
+    Start {
+        #[arg(value_name("BAD"))]
+        bad: Option<String>,
+
+        #[arg(value_name("GOOD"))]
+        good: Vec<String>,
+    },
+
+    /// Mark the revision, the current commit by default, as one without the regression
+    Good {
+        #[arg(value_name("REVISION"))]
+        revision: Option<String>,
+    },
+
+    /// Mark the revision, the current commit by default, as one with the regression
+    Bad {
+        #[arg(value_name("REVISION"))]
+        revision: Option<String>,
+    },
+
+    /// Mark the revision, the current commit by default, as one that cannot be tested
+    Skip {
+        #[arg(value_name("REVISION"))]
+        revision: Option<String>,
+    },
+
+    /// Finish the search and return to the commit it was started from
+    Reset,
+
+    /// Test the commits automatically: exit code 0 is good, 125 is skip, up to 127 is bad
+    Run {
+        #[arg(
+            value_name("COMMAND"),
+            required(true),
+            trailing_var_arg(true),
+            allow_hyphen_values(true)
+        )]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum OpCommands {
+    /// List the operations, the one the repository is at is marked with "@"
     Log,
 }
+
+#[derive(Subcommand)]
+pub enum StashCommands {
+    /// Save local changes to a new stash entry. This is the default
+    Push {
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+
+    /// List the stash entries
+    List,
+
+    /// Show the changes recorded in the stash entry
+    Show {
+        #[arg(
+            value_name("STASH"),
+            default_value("0"),
+            value_parser(parse_stash_index)
+        )]
+        index: usize,
+    },
+
+    /// Apply the stash entry on top of the working tree
+    Apply {
+        #[arg(
+            value_name("STASH"),
+            default_value("0"),
+            value_parser(parse_stash_index)
+        )]
+        index: usize,
+    },
+
+    /// Apply the stash entry and drop it if there are no conflicts
+    Pop {
+        #[arg(
+            value_name("STASH"),
+            default_value("0"),
+            value_parser(parse_stash_index)
+        )]
+        index: usize,
+    },
+
+    /// Remove the stash entry
+    Drop {
+        #[arg(
+            value_name("STASH"),
+            default_value("0"),
+            value_parser(parse_stash_index)
+        )]
+        index: usize,
+    },
+}
+
+/// Accepts both "N" and "stash@{N}"
+fn parse_stash_index(s: &str) -> Result<usize, String> {
+    let index = s
+        .strip_prefix("stash@{")
+        .and_then(|x| x.strip_suffix('}'))
+        .unwrap_or(s);
+    index
+        .parse()
+        .map_err(|_| format!("invalid stash entry {}", s))
+}
+
+/// Accepts a percentage or "off", the value is kept as is
+fn parse_threshold(s: &str) -> Result<String, String> {
+    parse_rename_threshold(s)
+        .map(|_| s.to_owned())
+        .map_err(|_| format!("invalid rename threshold {}", s))
+}
+
+fn parse_line_range(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = s
+        .split_once(',')
+        .ok_or_else(|| "expected range in the form START,END".to_owned())?;
+    let start = start
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid line number {}", start))?;
+    let end = end
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid line number {}", end))?;
+    Ok((start, end))
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::{create_dir_all, File};
 use std::io::{copy, prelude::Read, Write};
 use std::path::Path;
@@ -46,7 +47,7 @@ pub fn zip(src_dir: &Path, dst_archive: &Path) -> Result<(), ZipError> {
             zip_writer.start_file(name_as_str, options)?;
             let mut file = File::open(path)?;
             file.read_to_end(&mut buffer)?;
-            zip_writer.write_all(&*buffer)?;
+            zip_writer.write_all(&buffer)?;
             buffer.clear();
         } else if !name_as_path.as_os_str().is_empty() {
             zip_writer.add_directory(name_as_str, FileOptions::default())?;
@@ -72,7 +73,7 @@ pub fn unzip(src_archive: &Path, dst_dir: &Path) -> Result<(), ZipError> {
         } else {
             if let Some(par) = path.parent() {
                 if !par.try_exists()? {
-                    create_dir_all(&par)?;
+                    create_dir_all(par)?;
                 }
             }
             let mut outfile = File::create(&path)?;
@@ -93,6 +94,71 @@ pub fn unzip(src_archive: &Path, dst_dir: &Path) -> Result<(), ZipError> {
     Ok(())
 }
 
+/// Reads the contents of a single file from the given zip archive
+///
+/// Returns None if there is no such file in the archive
+pub fn read_file(src_archive: &Path, name: &str) -> Result<Option<Vec<u8>>, ZipError> {
+    let mut archive = zip::ZipArchive::new(File::open(src_archive)?)?;
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    Ok(Some(buffer))
+}
+
+/// Reads the contents of all files from the given zip archive
+///
+/// Keys are the file names inside the archive
+pub fn read_files(src_archive: &Path) -> Result<BTreeMap<String, Vec<u8>>, ZipError> {
+    read_files_matching(src_archive, |_, _| true)
+}
+
+/// Reads the contents of the files for which "keep" returns true given their name and size
+pub fn read_files_matching(
+    src_archive: &Path,
+    keep: impl Fn(&str, u64) -> bool,
+) -> Result<BTreeMap<String, Vec<u8>>, ZipError> {
+    let mut archive = zip::ZipArchive::new(File::open(src_archive)?)?;
+    let mut files = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() || !keep(file.name(), file.size()) {
+            continue;
+        }
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        files.insert(file.name().to_owned(), buffer);
+    }
+    Ok(files)
+}
+
+/// Compares the files of the archive with the files under "dir" one by one
+///
+/// Maps the name of every archived file to whether the file under "dir" is the same.
+/// Files absent under "dir" are different
+pub fn compare_files(src_archive: &Path, dir: &Path) -> Result<BTreeMap<String, bool>, ZipError> {
+    let mut archive = zip::ZipArchive::new(File::open(src_archive)?)?;
+    let mut result = BTreeMap::new();
+    let mut archived = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = dir.join(file.name());
+        let same = path.is_file() && path.metadata()?.len() == file.size() && {
+            archived.clear();
+            file.read_to_end(&mut archived)?;
+            std::fs::read(&path)? == archived
+        };
+        result.insert(file.name().to_owned(), same);
+    }
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -102,14 +168,14 @@ mod tests {
         let path_archive = std::path::Path::new("temp_archive");
         let contents = "abbbcc";
 
-        std::fs::create_dir(&path_dir)?;
+        std::fs::create_dir(path_dir)?;
         std::fs::write(&path_file, contents)?;
-        super::zip(&path_dir, &path_archive)?;
-        std::fs::remove_dir_all(&path_dir)?;
-        super::unzip(&path_archive, &path_dir)?;
-        std::fs::remove_file(&path_archive)?;
+        super::zip(path_dir, path_archive)?;
+        std::fs::remove_dir_all(path_dir)?;
+        super::unzip(path_archive, path_dir)?;
+        std::fs::remove_file(path_archive)?;
         assert_eq!(std::fs::read_to_string(&path_file)?, contents);
-        std::fs::remove_dir_all(&path_dir)?;
+        std::fs::remove_dir_all(path_dir)?;
         Ok(())
     }
 }
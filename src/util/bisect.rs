@@ -0,0 +1,88 @@
+use super::graph::{ancestors, history};
+use super::hash::VcsHash;
+use super::vcs_state::VcsState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{remove_file, File};
+use std::io::{BufReader, BufWriter, Error};
+use std::path::{Path, PathBuf};
+
+/// A search for the first bad commit, it is stored in "<repos_root>/.vcs" until reset
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct BisectState {
+    /// The commit and the branch to return to when the search is over
+    pub orig_head: VcsHash,
+    pub orig_branch: String,
+    pub bad: Option<VcsHash>,
+    pub good: Vec<VcsHash>,
+    /// Commits that cannot be tested
+    pub skipped: Vec<VcsHash>,
+}
+
+impl BisectState {
+    fn path(repos_root: &Path) -> PathBuf {
+        repos_root.join(".vcs").join("bisect_state.json")
+    }
+
+    /// Loads the bisect state if a search is in progress
+    pub fn load(repos_root: &Path) -> Result<Option<Self>, Error> {
+        let path = Self::path(repos_root);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(
+            path,
+        )?))?))
+    }
+
+    /// Stores the bisect state at "<repos_root>/.vcs/"
+    pub fn update_vcs_dir(&self, repos_root: &Path) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(Self::path(repos_root))?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Marks the search as finished
+    pub fn remove(repos_root: &Path) -> Result<(), Error> {
+        remove_file(Self::path(repos_root))
+    }
+
+    /// Lists commits the search refers to
+    pub fn hashes(&self) -> impl Iterator<Item = VcsHash> + '_ {
+        std::iter::once(self.orig_head)
+            .chain(self.bad)
+            .chain(self.good.iter().chain(self.skipped.iter()).copied())
+    }
+
+    /// Lists commits that may be the first bad one, the latest go first
+    ///
+    /// These are the bad commit and its ancestors that are not ancestors of any good commit
+    pub fn candidates(&self, state: &VcsState, bad: &VcsHash) -> Vec<VcsHash> {
+        let good = ancestors(state, &self.good);
+        history(state, &[*bad], false)
+            .into_iter()
+            .filter(|x| !good.contains(x))
+            .collect()
+    }
+}
+
+/// Chooses the commit to test next among "untested" ones
+///
+/// The chosen commit splits "candidates" into its ancestors and the rest as evenly as possible.
+/// Returns it with the number of candidates among its ancestors, itself included
+pub fn midpoint(
+    state: &VcsState,
+    candidates: &[VcsHash],
+    untested: &[VcsHash],
+) -> Option<(VcsHash, usize)> {
+    let candidates_set: HashSet<&VcsHash> = candidates.iter().collect();
+    untested
+        .iter()
+        .map(|hash| {
+            let reach = ancestors(state, &[*hash])
+                .iter()
+                .filter(|x| candidates_set.contains(x))
+                .count();
+            (*hash, reach)
+        })
+        .min_by_key(|(_, reach)| (*reach).max(candidates.len() - reach))
+}
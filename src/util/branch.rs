@@ -2,8 +2,10 @@ use super::hash::VcsHash;
 use serde::{Deserialize, Serialize};
 
 /// Stores state of a branch
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Branch {
     pub name: String,
     pub commit_hash: VcsHash,
+    #[serde(default)]
+    pub description: Option<String>,
 }
@@ -1,16 +1,52 @@
 use super::hash::VcsHash;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Stores commit data
 #[derive(Deserialize, Serialize, Clone)]
+#[serde(from = "StoredCommit")]
 pub struct Commit {
     pub branch_name: String,
     pub time: DateTime<Local>,
     pub message: String,
     pub changes: CommitChanges,
     pub hash: VcsHash,
-    pub parent: VcsHash,
+    /// The first parent is the commit the changes are made on, others are merged into it.
+    /// The initial commit has no parents
+    pub parents: Vec<VcsHash>,
+}
+
+/// Commit data as stored in "status.json" by any version
+#[derive(Deserialize)]
+struct StoredCommit {
+    branch_name: String,
+    time: DateTime<Local>,
+    message: String,
+    changes: CommitChanges,
+    hash: VcsHash,
+    #[serde(default)]
+    parents: Vec<VcsHash>,
+    /// The only parent stored before merge commits, zero hash for the initial commit
+    #[serde(default)]
+    parent: Option<VcsHash>,
+}
+
+impl From<StoredCommit> for Commit {
+    fn from(stored: StoredCommit) -> Self {
+        let mut parents = stored.parents;
+        if parents.is_empty() {
+            parents.extend(stored.parent.filter(|x| *x != VcsHash::zero()));
+        }
+        Commit {
+            branch_name: stored.branch_name,
+            time: stored.time,
+            message: stored.message,
+            changes: stored.changes,
+            hash: stored.hash,
+            parents,
+        }
+    }
 }
 
 /// Represents changes in the working tree
@@ -19,10 +55,97 @@ pub struct CommitChanges {
     pub modified: Vec<String>,
     pub added: Vec<String>,
     pub deleted: Vec<String>,
+    #[serde(default)]
+    pub renamed: Vec<FileMove>,
+    #[serde(default)]
+    pub copied: Vec<FileMove>,
+}
+
+/// A file moved or copied to another path, possibly with changes
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileMove {
+    pub from: String,
+    pub to: String,
+    /// Percentage of lines kept
+    pub similarity: u8,
+}
+
+impl Commit {
+    /// The commit the changes are made on, zero hash for the initial commit
+    pub fn first_parent(&self) -> VcsHash {
+        self.parents.first().copied().unwrap_or_else(VcsHash::zero)
+    }
+
+    /// Describes the commit to be created for naming its snapshot, see create_snapshot
+    pub fn snapshot_header(parents: &[VcsHash], time: &DateTime<Local>, message: &str) -> String {
+        let parents: Vec<String> = parents.iter().map(|x| x.to_string()).collect();
+        format!("{}\n{}\n{}", parents.join(" "), time.to_rfc3339(), message)
+    }
 }
 
 impl CommitChanges {
+    /// Compares two trees file by file without rename detection
+    pub fn between(old: &BTreeMap<String, Vec<u8>>, new: &BTreeMap<String, Vec<u8>>) -> Self {
+        let mut changes = Self::default();
+        for (rel_path, contents) in old {
+            match new.get(rel_path) {
+                Some(x) if x != contents => changes.modified.push(rel_path.clone()),
+                Some(_) => {}
+                None => changes.deleted.push(rel_path.clone()),
+            }
+        }
+        changes.added = new
+            .keys()
+            .filter(|x| !old.contains_key(*x))
+            .cloned()
+            .collect();
+        changes
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.modified.is_empty() && self.added.is_empty() && self.deleted.is_empty()
+        self.modified.is_empty()
+            && self.added.is_empty()
+            && self.deleted.is_empty()
+            && self.renamed.is_empty()
+            && self.copied.is_empty()
+    }
+
+    /// Lists all changed paths, both the old and the new path of renamed files
+    pub fn paths(&self) -> Vec<&String> {
+        let mut result: Vec<&String> = (self.modified.iter())
+            .chain(self.added.iter())
+            .chain(self.deleted.iter())
+            .chain(self.renamed.iter().flat_map(|x| [&x.from, &x.to]))
+            .chain(self.copied.iter().map(|x| &x.to))
+            .collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_parent_migration() {
+        let stored = |hash: char, parent: char| -> String {
+            format!(
+                r#"{{"branch_name": "master", "time": "2024-01-01T00:00:00+00:00",
+                "message": "m", "changes": {{"modified": [], "added": [], "deleted": []}},
+                "hash": "{}", "parent": "{}"}}"#,
+                hash.to_string().repeat(40),
+                parent.to_string().repeat(40)
+            )
+        };
+        let initial: Commit = serde_json::from_str(&stored('a', '0')).unwrap();
+        assert!(initial.parents.is_empty());
+        let commit: Commit = serde_json::from_str(&stored('b', 'a')).unwrap();
+        assert!(commit.parents == [initial.hash]);
+
+        let reloaded: Commit =
+            serde_json::from_str(&serde_json::to_string(&commit).unwrap()).unwrap();
+        assert!(reloaded.parents == commit.parents);
     }
 }
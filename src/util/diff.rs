@@ -0,0 +1,291 @@
+/// Finds the longest common subsequence of two sequences
+///
+/// Returns pairs of matching indices (index in "old", index in "new") in increasing order.
+/// Common prefix and suffix are matched directly, the rest is handled by Myers' algorithm.
+pub fn common_subsequence<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(x, y)| x == y).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut result: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    result.extend(
+        myers(old_mid, new_mid)
+            .into_iter()
+            .map(|(i, j)| (i + prefix, j + prefix)),
+    );
+    result.extend((0..suffix).map(|i| (old.len() - suffix + i, new.len() - suffix + i)));
+    result
+}
+
+/// Myers' O(ND) difference algorithm with backtracking through the saved frontiers
+fn myers<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m) as usize;
+    if max == 0 {
+        return vec![];
+    }
+    let offset = max as isize;
+    // the furthest x reached on the diagonal k is stored in v[k + offset]
+    let mut v = vec![0isize; 2 * max + 2];
+    let mut trace = Vec::new();
+    'search: for d in 0..=offset {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut matches = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+    matches.reverse();
+    matches
+}
+
+/// Splits text into lines keeping the line terminators
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// How conflicting hunks of a three-way merge are presented
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStyle {
+    /// Both sides separated by "=======" only
+    #[default]
+    Merge,
+    /// The base version is shown between the sides as well
+    Diff3,
+}
+
+/// Which side wins conflicting hunks of a three-way merge
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Favor {
+    /// Conflicting hunks are written with conflict markers
+    #[default]
+    None,
+    Ours,
+    Theirs,
+}
+
+/// Result of a three-way text merge
+pub struct TextMerge {
+    pub text: String,
+    /// Number of conflicting hunks written with markers
+    pub conflicts: usize,
+}
+
+/// Merges the changes made in "ours" and "theirs" relative to "base" line by line
+///
+/// Non-overlapping changes are combined, overlapping ones are resolved in favor of one side
+/// or written with conflict markers labelled with "labels" (ours, theirs)
+pub fn merge_three_way(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    labels: (&str, &str),
+    style: ConflictStyle,
+    favor: Favor,
+) -> TextMerge {
+    let base = split_lines(base);
+    let ours = split_lines(ours);
+    let theirs = split_lines(theirs);
+    let mut ours_of = vec![None; base.len()];
+    for (i, j) in common_subsequence(&base, &ours) {
+        ours_of[i] = Some(j);
+    }
+    let mut theirs_of = vec![None; base.len()];
+    for (i, j) in common_subsequence(&base, &theirs) {
+        theirs_of[i] = Some(j);
+    }
+
+    let mut result = TextMerge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    let (mut ib, mut io, mut it) = (0, 0, 0);
+    loop {
+        // the stable chunk: lines kept unchanged on both sides
+        let mut len = 0;
+        while ib + len < base.len()
+            && ours_of[ib + len] == Some(io + len)
+            && theirs_of[ib + len] == Some(it + len)
+        {
+            len += 1;
+        }
+        for line in &base[ib..ib + len] {
+            result.text.push_str(line);
+        }
+        ib += len;
+        io += len;
+        it += len;
+        if ib == base.len() && io == ours.len() && it == theirs.len() {
+            break;
+        }
+
+        // the unstable chunk lasts up to the next base line kept on both sides
+        let (jb, jo, jt) = (ib..base.len())
+            .find_map(|i| match (ours_of[i], theirs_of[i]) {
+                (Some(o), Some(t)) => Some((i, o, t)),
+                _ => None,
+            })
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let chunk_base = &base[ib..jb];
+        let chunk_ours = &ours[io..jo];
+        let chunk_theirs = &theirs[it..jt];
+        if chunk_ours == chunk_base || chunk_ours == chunk_theirs {
+            chunk_theirs.iter().for_each(|x| result.text.push_str(x));
+        } else if chunk_theirs == chunk_base || favor == Favor::Ours {
+            chunk_ours.iter().for_each(|x| result.text.push_str(x));
+        } else if favor == Favor::Theirs {
+            chunk_theirs.iter().for_each(|x| result.text.push_str(x));
+        } else {
+            result.conflicts += 1;
+            push_conflict_part(
+                &mut result.text,
+                &format!("<<<<<<< {}", labels.0),
+                chunk_ours,
+            );
+            if style == ConflictStyle::Diff3 {
+                push_conflict_part(&mut result.text, "||||||| base", chunk_base);
+            }
+            push_conflict_part(&mut result.text, "=======", chunk_theirs);
+            result.text.push_str(&format!(">>>>>>> {}\n", labels.1));
+        }
+        (ib, io, it) = (jb, jo, jt);
+    }
+    result
+}
+
+fn push_conflict_part(text: &mut String, marker: &str, lines: &[&str]) {
+    text.push_str(marker);
+    text.push('\n');
+    for line in lines {
+        text.push_str(line);
+    }
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_subsequence, merge_three_way, split_lines, ConflictStyle, Favor};
+
+    #[test]
+    fn test_common_subsequence() {
+        let old = split_lines("a\nb\nc\nd\ne\n");
+        let new = split_lines("a\nx\nc\nd\ny\ne\n");
+        assert_eq!(
+            common_subsequence(&old, &new),
+            vec![(0, 0), (2, 2), (3, 3), (4, 5)]
+        );
+        assert_eq!(common_subsequence::<&str>(&[], &new), vec![]);
+        assert_eq!(common_subsequence(&old, &[]), vec![]);
+        assert_eq!(
+            common_subsequence(&old, &old),
+            (0..old.len()).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+        let old = ["x", "a", "b", "c"];
+        let new = ["a", "b", "y", "c", "z"];
+        assert_eq!(common_subsequence(&old, &new), vec![(1, 0), (2, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn test_merge_three_way() {
+        let base = "a\nb\nc\nd\ne\n";
+        let merged = merge_three_way(
+            base,
+            "a\nB\nc\nd\ne\n",
+            "a\nb\nc\nd\nE\nf\n",
+            ("ours", "theirs"),
+            ConflictStyle::Merge,
+            Favor::None,
+        );
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.text, "a\nB\nc\nd\nE\nf\n");
+
+        let merged = merge_three_way(
+            base,
+            "a\nX\nc\nd\ne\n",
+            "a\nY\nc\nd\ne",
+            ("ours", "theirs"),
+            ConflictStyle::Merge,
+            Favor::None,
+        );
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.text,
+            "a\n<<<<<<< ours\nX\n=======\nY\n>>>>>>> theirs\nc\nd\ne"
+        );
+
+        let merged = merge_three_way(
+            base,
+            "a\nX\nc\nd\ne\n",
+            "a\nY\nc\nd\ne\n",
+            ("ours", "theirs"),
+            ConflictStyle::Diff3,
+            Favor::None,
+        );
+        assert_eq!(
+            merged.text,
+            "a\n<<<<<<< ours\nX\n||||||| base\nb\n=======\nY\n>>>>>>> theirs\nc\nd\ne\n"
+        );
+
+        for (favor, expected) in [
+            (Favor::Ours, "a\nX\nc\nD\ne\n"),
+            (Favor::Theirs, "a\nY\nc\nD\ne\n"),
+        ] {
+            let merged = merge_three_way(
+                base,
+                "a\nX\nc\nd\ne\n",
+                "a\nY\nc\nD\ne\n",
+                ("ours", "theirs"),
+                ConflictStyle::Merge,
+                favor,
+            );
+            assert_eq!(merged.conflicts, 0);
+            assert_eq!(merged.text, expected);
+        }
+    }
+}
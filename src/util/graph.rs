@@ -0,0 +1,99 @@
+use super::hash::VcsHash;
+use super::vcs_state::VcsState;
+use std::collections::{HashMap, HashSet};
+
+/// Collects the given commits and all their ancestors
+pub fn ancestors(state: &VcsState, start: &[VcsHash]) -> HashSet<VcsHash> {
+    let mut visited = HashSet::new();
+    let mut stack: Vec<VcsHash> = start.to_vec();
+    while let Some(hash) = stack.pop() {
+        if hash == VcsHash::zero() || !visited.insert(hash) {
+            continue;
+        }
+        if let Some(commit) = state.find_commit(&hash) {
+            stack.extend(commit.parents.iter());
+        }
+    }
+    visited
+}
+
+/// Finds the best common ancestor of two commits
+///
+/// A common ancestor is the best if it is not an ancestor of another common ancestor.
+/// If there are several of them the latest one is chosen
+pub fn merge_base(state: &VcsState, a: &VcsHash, b: &VcsHash) -> Option<VcsHash> {
+    merge_base_of_set(state, &[*a], b)
+}
+
+/// Finds the best common ancestor of the commit "b" and any of the commits "a"
+pub fn merge_base_of_set(state: &VcsState, a: &[VcsHash], b: &VcsHash) -> Option<VcsHash> {
+    let ancestors_a = ancestors(state, a);
+    let common: HashSet<VcsHash> = ancestors(state, &[*b])
+        .into_iter()
+        .filter(|x| ancestors_a.contains(x))
+        .collect();
+    let mut best = common.clone();
+    for hash in common.iter() {
+        if let Some(commit) = state.find_commit(hash) {
+            for ancestor in ancestors(state, &commit.parents) {
+                best.remove(&ancestor);
+            }
+        }
+    }
+    best.into_iter()
+        .filter_map(|x| state.find_commit(&x))
+        .max_by_key(|x| x.time)
+        .map(|x| x.hash)
+}
+
+/// Lists the given commits and all their ancestors, children always go before parents
+///
+/// Among the commits ready to be listed the latest one goes first.
+/// With "first_parent" only the first parent of each commit is followed
+pub fn history(state: &VcsState, start: &[VcsHash], first_parent: bool) -> Vec<VcsHash> {
+    let parents_of = |hash: &VcsHash| -> Vec<VcsHash> {
+        match state.find_commit(hash) {
+            Some(commit) if first_parent => commit.parents.iter().take(1).copied().collect(),
+            Some(commit) => commit.parents.clone(),
+            None => vec![],
+        }
+    };
+    // number of children of each commit inside the listed part of the graph
+    let mut children = HashMap::<VcsHash, usize>::new();
+    let mut visited = HashSet::new();
+    let mut stack: Vec<VcsHash> = start.to_vec();
+    while let Some(hash) = stack.pop() {
+        if !visited.insert(hash) {
+            continue;
+        }
+        for parent in parents_of(&hash) {
+            *children.entry(parent).or_default() += 1;
+            stack.push(parent);
+        }
+    }
+
+    let mut ready: Vec<VcsHash> = vec![];
+    for hash in start {
+        if !children.contains_key(hash) && !ready.contains(hash) {
+            ready.push(*hash);
+        }
+    }
+    let mut result = vec![];
+    while let Some((i, _)) = ready
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, x)| state.find_commit(x).map(|x| x.time))
+    {
+        let hash = ready.swap_remove(i);
+        result.push(hash);
+        for parent in parents_of(&hash) {
+            // unwrap: every parent is counted above
+            let count = children.get_mut(&parent).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push(parent);
+            }
+        }
+    }
+    result
+}
@@ -61,11 +61,16 @@ impl VcsHash {
         let mut file = File::open(path)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
+        Ok(Self::from_bytes(&buffer))
+    }
+
+    /// Calculates SHA-1 hash from the given bytes
+    pub fn from_bytes(bytes: &[u8]) -> VcsHash {
         let mut hasher = Sha1::new();
-        hasher.update(buffer);
+        hasher.update(bytes);
         // unwrap: length is always 20
         let slice = hasher.finalize().to_vec().try_into().unwrap();
-        Ok(VcsHash(slice))
+        VcsHash(slice)
     }
 
     /// Shortens hash string representation
@@ -0,0 +1,311 @@
+use super::diff::{merge_three_way, ConflictStyle, Favor};
+use super::hash::VcsHash;
+use super::rename::find_renames;
+use super::snapshot::path_matches;
+use indoc::indoc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read, remove_file, File};
+use std::io::{BufReader, BufWriter, Error};
+use std::path::{Path, PathBuf};
+
+/// Result of a three-way merge of snapshots
+#[derive(Default)]
+pub struct TreeMerge {
+    /// Merged files, conflicting ones contain conflict markers or "ours" version
+    pub files: BTreeMap<String, Vec<u8>>,
+    /// Files changed on both sides and merged line by line without conflicts
+    pub auto_merged: Vec<String>,
+    /// Files with unresolved conflicts
+    pub conflicts: Vec<String>,
+    /// Conflicting files without conflict markers: binary ones and ones deleted on one side
+    pub without_markers: Vec<String>,
+}
+
+/// Merges the changes made in "ours" and "theirs" snapshots relative to "base"
+///
+/// A file changed differently on both sides is merged line by line if it is a text file
+/// present on both sides. Otherwise the side given by "favor" is taken,
+/// without one it is a conflict and "ours" version is kept.
+/// With "rename_threshold" a file renamed on one side takes the changes made on the other
+pub fn merge_trees(
+    base: &BTreeMap<String, Vec<u8>>,
+    ours: &BTreeMap<String, Vec<u8>>,
+    theirs: &BTreeMap<String, Vec<u8>>,
+    labels: (&str, &str),
+    style: ConflictStyle,
+    favor: Favor,
+    rename_threshold: Option<u8>,
+) -> TreeMerge {
+    let (mut base, mut ours, mut theirs) = (base.clone(), ours.clone(), theirs.clone());
+    if let Some(threshold) = rename_threshold {
+        follow_renames(&mut base, &mut ours, &mut theirs, threshold);
+    }
+    let (base, ours, theirs) = (&base, &ours, &theirs);
+    let paths: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut result = TreeMerge::default();
+    for path in paths {
+        let ancestor = base.get(path);
+        let current = ours.get(path);
+        let other = theirs.get(path);
+        let contents = if current == other || ancestor == other {
+            current.cloned()
+        } else if ancestor == current {
+            other.cloned()
+        } else {
+            let texts = (
+                std::str::from_utf8(ancestor.map_or(&[], |x| x.as_slice())),
+                current.map(|x| std::str::from_utf8(x)),
+                other.map(|x| std::str::from_utf8(x)),
+            );
+            match texts {
+                (Ok(ancestor), Some(Ok(current)), Some(Ok(other))) => {
+                    let merged = merge_three_way(ancestor, current, other, labels, style, favor);
+                    if merged.conflicts == 0 {
+                        result.auto_merged.push(path.clone());
+                    } else {
+                        result.conflicts.push(path.clone());
+                    }
+                    Some(merged.text.into_bytes())
+                }
+                _ if favor == Favor::Ours => current.cloned(),
+                _ if favor == Favor::Theirs => other.cloned(),
+                _ => {
+                    result.conflicts.push(path.clone());
+                    result.without_markers.push(path.clone());
+                    current.cloned()
+                }
+            }
+        };
+        if let Some(contents) = contents {
+            result.files.insert(path.clone(), contents);
+        }
+    }
+    result
+}
+
+/// Moves files renamed on one side to their new paths on the other side and in the base
+///
+/// Renames of the same file to different paths on both sides are left as they are
+fn follow_renames(
+    base: &mut BTreeMap<String, Vec<u8>>,
+    ours: &mut BTreeMap<String, Vec<u8>>,
+    theirs: &mut BTreeMap<String, Vec<u8>>,
+    threshold: u8,
+) {
+    let ours_renames = find_renames(base, ours, threshold);
+    let theirs_renames = find_renames(base, theirs, threshold);
+    let move_file = |tree: &mut BTreeMap<String, Vec<u8>>, from: &String, to: &String| {
+        if let Some(contents) = tree.remove(from) {
+            tree.insert(to.clone(), contents);
+        }
+    };
+    for (from, to) in ours_renames.iter() {
+        match theirs_renames.get(from) {
+            Some(x) if x == to => move_file(base, from, to),
+            Some(_) => {}
+            None if !theirs.contains_key(to) => {
+                move_file(base, from, to);
+                move_file(theirs, from, to);
+            }
+            None => {}
+        }
+    }
+    for (from, to) in theirs_renames.iter() {
+        if !ours_renames.contains_key(from) && !ours.contains_key(to) {
+            move_file(base, from, to);
+            move_file(ours, from, to);
+        }
+    }
+}
+
+/// Checks whether the file still contains conflict markers
+pub fn has_conflict_markers(contents: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(contents);
+    let mut lines = text.lines();
+    lines.any(|x| x.starts_with("<<<<<<< "))
+        && lines.any(|x| x == "=======")
+        && lines.any(|x| x.starts_with(">>>>>>> "))
+}
+
+/// Conflicting files of a stopped merge or rebase step
+#[derive(Deserialize, Serialize, Default)]
+pub struct Conflicts {
+    #[serde(rename = "conflicts")]
+    pub paths: Vec<String>,
+    /// Conflicting files without conflict markers: binary ones and ones deleted on one side
+    #[serde(default)]
+    pub without_markers: Vec<String>,
+    /// Files marked as resolved with "resolve" or "checkout --ours/--theirs"
+    #[serde(default)]
+    pub resolved: Vec<String>,
+}
+
+impl From<&TreeMerge> for Conflicts {
+    fn from(tree_merge: &TreeMerge) -> Self {
+        Conflicts {
+            paths: tree_merge.conflicts.clone(),
+            without_markers: tree_merge.without_markers.clone(),
+            resolved: vec![],
+        }
+    }
+}
+
+impl Conflicts {
+    /// Lists conflicting files that are not resolved yet
+    ///
+    /// A file with conflict markers is resolved once they are removed,
+    /// a file without them only when it is marked as resolved
+    pub fn unresolved(&self, repos_root: &Path) -> Result<Vec<String>, Error> {
+        let mut result = vec![];
+        for rel_path in self.paths.iter().filter(|x| !self.resolved.contains(x)) {
+            let path = repos_root.join(rel_path);
+            if self.without_markers.contains(rel_path)
+                || (path.is_file() && has_conflict_markers(&read(path)?))
+            {
+                result.push(rel_path.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Fails with the list of unresolved files if there are any
+    pub fn ensure_resolved(&self, repos_root: &Path) -> Result<(), Error> {
+        let unresolved = self.unresolved(repos_root)?;
+        if unresolved.is_empty() {
+            return Ok(());
+        }
+        let mut result = "error: the following conflicts are not resolved:\n".to_owned();
+        for rel_path in unresolved {
+            result.push_str(&format!("  {}\n", rel_path));
+        }
+        result.push_str(indoc! {
+        "Remove the conflict markers or mark the files with resolve before you continue.
+        Aborting..."});
+        Err(Error::other(result))
+    }
+
+    /// Marks the conflicting files matching "paths" as resolved, returns them
+    pub fn mark_resolved(&mut self, paths: &[String]) -> Vec<String> {
+        let selected: Vec<String> = (self.paths.iter())
+            .filter(|x| path_matches(x, paths))
+            .cloned()
+            .collect();
+        for rel_path in selected.iter() {
+            if !self.resolved.contains(rel_path) {
+                self.resolved.push(rel_path.clone());
+            }
+        }
+        selected
+    }
+}
+
+/// The command that started a merge, only the same command continues or aborts it
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeKind {
+    #[default]
+    Merge,
+    CherryPick,
+    Revert,
+}
+
+impl MergeKind {
+    pub fn command(&self) -> &'static str {
+        match self {
+            MergeKind::Merge => "merge",
+            MergeKind::CherryPick => "cherry-pick",
+            MergeKind::Revert => "revert",
+        }
+    }
+}
+
+/// A merge stopped because of conflicts, it is stored in "<repos_root>/.vcs" until finished
+#[derive(Deserialize, Serialize)]
+pub struct MergeState {
+    /// The current commit before the merge
+    pub head: VcsHash,
+    /// Commits being merged into the current one
+    pub merged: Vec<VcsHash>,
+    /// The commit whose files "checkout --theirs" takes, None if there is no such commit
+    #[serde(default)]
+    pub theirs: Option<VcsHash>,
+    pub message: String,
+    #[serde(flatten)]
+    pub conflicts: Conflicts,
+    /// Commits of a cherry-pick or revert left to apply after the merge is finished
+    #[serde(default)]
+    pub remaining: Vec<VcsHash>,
+    #[serde(default)]
+    pub kind: MergeKind,
+}
+
+impl MergeState {
+    fn path(repos_root: &Path) -> PathBuf {
+        repos_root.join(".vcs").join("merge_state.json")
+    }
+
+    /// Loads the merge state if a merge is in progress
+    pub fn load(repos_root: &Path) -> Result<Option<Self>, Error> {
+        let path = Self::path(repos_root);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(
+            path,
+        )?))?))
+    }
+
+    /// Loads the merge state started by the "kind" command, fails if there is none
+    pub fn load_started_by(repos_root: &Path, kind: MergeKind) -> Result<Self, Error> {
+        let merge_state = match Self::load(repos_root)? {
+            Some(x) => x,
+            None => {
+                return Err(Error::other(format!(
+                    "error: there is no {} in progress",
+                    kind.command()
+                )))
+            }
+        };
+        if merge_state.kind != kind {
+            return Err(Error::other(format!(
+                indoc! {
+                "error: a {0} is in progress, not a {1}.
+                Use {0} --continue to finish it or {0} --abort to drop it.
+                Aborting..."},
+                merge_state.kind.command(),
+                kind.command()
+            )));
+        }
+        Ok(merge_state)
+    }
+
+    /// Stores the merge state at "<repos_root>/.vcs/"
+    pub fn update_vcs_dir(&self, repos_root: &Path) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(Self::path(repos_root))?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Marks the merge as finished
+    pub fn remove(repos_root: &Path) -> Result<(), Error> {
+        remove_file(Self::path(repos_root))
+    }
+}
+
+/// Fails if a merge stopped because of conflicts is not finished yet
+pub fn ensure_no_merge_in_progress(repos_root: &Path) -> Result<(), Error> {
+    if let Some(merge_state) = MergeState::load(repos_root)? {
+        return Err(Error::other(format!(
+            indoc! {
+            "error: a {0} is in progress.
+            Use {0} --continue to finish it or {0} --abort to drop it.
+            Aborting..."},
+            merge_state.kind.command()
+        )));
+    }
+    Ok(())
+}
@@ -1,6 +1,17 @@
 pub mod archiving;
+pub mod bisect;
 pub mod branch;
 pub mod commit;
+pub mod diff;
+pub mod graph;
 pub mod hash;
+pub mod merge;
+pub mod op_log;
+pub mod rebase;
+pub mod reflog;
+pub mod rename;
+pub mod revision;
 pub mod snapshot;
+pub mod stash;
+pub mod tag;
 pub mod vcs_state;
@@ -0,0 +1,129 @@
+use super::bisect::BisectState;
+use super::branch::Branch;
+use super::hash::VcsHash;
+use super::vcs_state::VcsState;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Error};
+use std::path::Path;
+
+/// Positions of HEAD and all branches and the bisect in progress right after a command
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Operation {
+    pub time: DateTime<Local>,
+    /// The command that made the operation, like "merge feature"
+    pub command: String,
+    pub head: VcsHash,
+    pub branch_name: String,
+    pub branches: Vec<Branch>,
+    #[serde(default)]
+    pub bisect: Option<BisectState>,
+}
+
+impl Operation {
+    /// Checks whether HEAD, branches of "state" and the bisect are the ones recorded in the operation
+    fn matches(&self, state: &VcsState, bisect: &Option<BisectState>) -> bool {
+        self.bisect == *bisect
+            && self.head == state.head
+            && self.branch_name == state.branch_name
+            && self.branches.len() == state.branches.len()
+            && self
+                .branches
+                .iter()
+                .zip(state.branches.iter())
+                .all(|(a, b)| a.name == b.name && a.commit_hash == b.commit_hash)
+    }
+
+    /// Lists commits HEAD, branches and the bisect point to
+    pub fn hashes(&self) -> impl Iterator<Item = VcsHash> + '_ {
+        std::iter::once(self.head)
+            .chain(self.branches.iter().map(|x| x.commit_hash))
+            .chain(self.bisect.iter().flat_map(|x| x.hashes()))
+    }
+}
+
+/// Operations made by the commands, the oldest goes first
+#[derive(Deserialize, Serialize, Default)]
+pub struct OpLog {
+    pub operations: Vec<Operation>,
+    /// Index of the operation the repository is at, the later ones are undone
+    pub current: usize,
+}
+
+impl OpLog {
+    /// Loads the operation log from "<repos_root>/.vcs", it is empty if nothing was recorded yet
+    pub fn load(repos_root: &Path) -> Result<Self, Error> {
+        let path = repos_root.join(".vcs").join("op_log.json");
+        if !path.try_exists()? {
+            return Ok(OpLog::default());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Updates the operation log at "<repos_root>/.vcs/"
+    pub fn update_vcs_dir(&self, repos_root: &Path) -> Result<(), Error> {
+        let file = File::create(repos_root.join(".vcs").join("op_log.json"))?;
+        let writer = std::io::BufWriter::new(file);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Appends the refs of "state" and the bisect as a new operation unless they have not changed
+    ///
+    /// Undone operations cannot be redone after that
+    pub fn record(&mut self, state: &VcsState, bisect: Option<BisectState>, command: &str) {
+        if self
+            .operations
+            .get(self.current)
+            .is_some_and(|x| x.matches(state, &bisect))
+        {
+            return;
+        }
+        self.operations.truncate(self.current + 1);
+        self.operations.push(Operation {
+            time: Local::now(),
+            command: command.to_owned(),
+            head: state.head,
+            branch_name: state.branch_name.clone(),
+            branches: state.branches.clone(),
+            bisect,
+        });
+        self.current = self.operations.len() - 1;
+    }
+
+    /// Drops operations that point to commits absent in "commits"
+    pub fn retain_existing(&mut self, commits: &HashSet<VcsHash>) {
+        if self.operations.is_empty() {
+            return;
+        }
+        let keep: Vec<bool> = (self.operations.iter())
+            .map(|x| x.hashes().all(|x| commits.contains(&x)))
+            .collect();
+        let kept_till_current = keep[..=self.current].iter().filter(|x| **x).count();
+        let mut keep = keep.into_iter();
+        // unwrap: "keep" has an element for every operation
+        self.operations.retain(|_| keep.next().unwrap());
+        self.current = kept_till_current.saturating_sub(1);
+    }
+}
+
+/// Records HEAD, branches and the bisect of the repository as an operation made by "command"
+pub fn record_operation(repos_root: &Path, command: &str) -> Result<(), Error> {
+    let state = VcsState::load(repos_root)?;
+    let bisect = BisectState::load(repos_root)?;
+    let mut op_log = OpLog::load(repos_root)?;
+    op_log.record(&state, bisect, command);
+    op_log.update_vcs_dir(repos_root)
+}
+
+/// Passes "result" through recording the operation made by "command" if it succeeded
+pub fn recorded(
+    repos_root: &Path,
+    command: &str,
+    result: Result<String, Error>,
+) -> Result<String, Error> {
+    let output = result?;
+    record_operation(repos_root, command)?;
+    Ok(output)
+}
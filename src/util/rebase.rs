@@ -0,0 +1,272 @@
+use super::hash::VcsHash;
+use super::merge::Conflicts;
+use indoc::indoc;
+use serde::{Deserialize, Serialize};
+use std::fs::{remove_file, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// A rebase in progress, it is stored in "<repos_root>/.vcs" until finished or aborted
+#[derive(Deserialize, Serialize)]
+pub struct RebaseState {
+    pub branch_name: String,
+    /// The last commit of the branch before the rebase
+    pub orig_head: VcsHash,
+    /// The commit the branch is rebased onto
+    pub onto: VcsHash,
+    /// Steps left to do, the next one goes first
+    pub todo: Vec<TodoStep>,
+    /// The step that stopped because of conflicts or to be edited
+    pub stopped: Option<TodoStep>,
+    #[serde(flatten)]
+    pub conflicts: Conflicts,
+    /// Whether the stopped step is already committed and waits for changes to amend it with
+    #[serde(default)]
+    pub editing: bool,
+}
+
+/// What to do with a commit during a rebase
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TodoAction {
+    Pick,
+    /// Pick the commit with another message
+    Reword,
+    /// Meld the commit into the previous one combining the messages
+    Squash,
+    /// Meld the commit into the previous one keeping its message
+    Fixup,
+    Drop,
+    /// Pick the commit and stop to amend it
+    Edit,
+}
+
+/// One line of the rebase todo list
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct TodoStep {
+    pub action: TodoAction,
+    pub hash: VcsHash,
+    /// The new message of a reworded commit
+    pub message: Option<String>,
+}
+
+impl TodoAction {
+    const ALL: [(TodoAction, &'static str, &'static str); 6] = [
+        (TodoAction::Pick, "pick", "p"),
+        (TodoAction::Reword, "reword", "r"),
+        (TodoAction::Squash, "squash", "s"),
+        (TodoAction::Fixup, "fixup", "f"),
+        (TodoAction::Drop, "drop", "d"),
+        (TodoAction::Edit, "edit", "e"),
+    ];
+
+    fn parse(word: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(_, name, short)| word == *name || word == *short)
+            .map(|(action, _, _)| *action)
+    }
+
+    fn name(&self) -> &'static str {
+        // unwrap: every action is listed in ALL
+        Self::ALL.iter().find(|x| x.0 == *self).unwrap().1
+    }
+}
+
+/// Writes the todo list as text, one "<action> <hash> <message>" line per commit
+///
+/// "commits" are (hash, message) pairs in the order of replay
+pub fn format_todo(commits: &[(VcsHash, String)]) -> String {
+    let mut result = String::new();
+    for (hash, message) in commits {
+        result.push_str(&format!("pick {} {}\n", hash.short_str(), message));
+    }
+    result.push_str(indoc! {"
+
+    # Commands:
+    # p, pick <commit> = use commit
+    # r, reword <commit> <message> = use commit with the message written after its hash
+    # s, squash <commit> = meld into previous commit combining the messages
+    # f, fixup <commit> = meld into previous commit keeping its message
+    # d, drop <commit> = remove commit
+    # e, edit <commit> = use commit, but stop to amend it
+    #
+    # Lines can be reordered, removing a line drops the commit.
+    # Lines starting with # are ignored. An empty list aborts the rebase.
+    "});
+    result
+}
+
+/// Parses the todo list, "resolve" turns a hash prefix into the commit hash
+///
+/// Dropped commits are left out. Squash and fixup cannot go first
+pub fn parse_todo(
+    text: &str,
+    resolve: impl Fn(&str) -> Result<VcsHash, Error>,
+) -> Result<Vec<TodoStep>, Error> {
+    let mut result = vec![];
+    let mut picked = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.splitn(3, char::is_whitespace);
+        // unwrap: a non-empty line has at least one word
+        let word = words.next().unwrap();
+        let action = TodoAction::parse(word).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "error: unknown command {} in line {} of the todo list",
+                    word,
+                    i + 1
+                ),
+            )
+        })?;
+        let hash = match words.next() {
+            Some(x) => resolve(x)?,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("error: missing commit in line {} of the todo list", i + 1),
+                ))
+            }
+        };
+        let message = words.next().map(|x| x.trim().to_owned());
+        match action {
+            TodoAction::Drop => continue,
+            TodoAction::Squash | TodoAction::Fixup if !picked => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "error: cannot {} without a previous commit in line {} of the todo list",
+                        action.name(),
+                        i + 1
+                    ),
+                ))
+            }
+            TodoAction::Reword if message.as_deref().unwrap_or("").is_empty() => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("error: missing message in line {} of the todo list", i + 1),
+                ))
+            }
+            _ => {}
+        }
+        picked = true;
+        result.push(TodoStep {
+            action,
+            hash,
+            message: if action == TodoAction::Reword {
+                message
+            } else {
+                None
+            },
+        });
+    }
+    Ok(result)
+}
+
+impl RebaseState {
+    fn path(repos_root: &Path) -> PathBuf {
+        repos_root.join(".vcs").join("rebase_state.json")
+    }
+
+    /// Loads the rebase state if a rebase is in progress
+    pub fn load(repos_root: &Path) -> Result<Option<Self>, Error> {
+        let path = Self::path(repos_root);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(
+            path,
+        )?))?))
+    }
+
+    /// Stores the rebase state at "<repos_root>/.vcs/"
+    pub fn update_vcs_dir(&self, repos_root: &Path) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(Self::path(repos_root))?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Marks the rebase as finished
+    pub fn remove(repos_root: &Path) -> Result<(), Error> {
+        remove_file(Self::path(repos_root))
+    }
+}
+
+/// Fails if a rebase is not finished yet
+pub fn ensure_no_rebase_in_progress(repos_root: &Path) -> Result<(), Error> {
+    if RebaseState::load(repos_root)?.is_some() {
+        return Err(Error::other(indoc! {
+        "error: a rebase is in progress.
+        Use rebase --continue, --skip or --abort to finish it.
+        Aborting..."}));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_todo, parse_todo, TodoAction, TodoStep};
+    use crate::util::hash::VcsHash;
+    use std::io::{Error, ErrorKind};
+
+    fn hashes() -> Vec<VcsHash> {
+        ["a", "b", "c"]
+            .iter()
+            .map(|x| serde_json::from_str(&format!("\"{}\"", x.repeat(40))).unwrap())
+            .collect()
+    }
+
+    fn resolve(prefix: &str) -> Result<VcsHash, Error> {
+        hashes()
+            .into_iter()
+            .find(|x| x.to_string().starts_with(prefix))
+            .ok_or_else(|| Error::from(ErrorKind::NotFound))
+    }
+
+    #[test]
+    fn test_todo() {
+        let hashes = hashes();
+        let commits: Vec<(VcsHash, String)> = hashes
+            .iter()
+            .zip(["first", "second", "third"])
+            .map(|(hash, message)| (*hash, message.to_owned()))
+            .collect();
+        let steps = parse_todo(&format_todo(&commits), resolve).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert!(steps.iter().all(|x| x.action == TodoAction::Pick));
+
+        let text = format!(
+            "r {} new message\n# comment\n\nf {}\ndrop {}\n",
+            hashes[0].short_str(),
+            hashes[2].short_str(),
+            hashes[1].short_str()
+        );
+        assert_eq!(
+            parse_todo(&text, resolve).unwrap(),
+            vec![
+                TodoStep {
+                    action: TodoAction::Reword,
+                    hash: hashes[0],
+                    message: Some("new message".to_owned()),
+                },
+                TodoStep {
+                    action: TodoAction::Fixup,
+                    hash: hashes[2],
+                    message: None,
+                },
+            ]
+        );
+
+        for text in [
+            format!("squash {}", hashes[0].short_str()),
+            format!("reword {}", hashes[0].short_str()),
+            format!("merge {}", hashes[0].short_str()),
+            "pick".to_owned(),
+        ] {
+            assert!(parse_todo(&text, resolve).is_err(), "{}", text);
+        }
+    }
+}
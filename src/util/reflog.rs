@@ -0,0 +1,152 @@
+use super::hash::VcsHash;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A single movement of HEAD or a branch
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ReflogEntry {
+    /// The commit the ref pointed to before, None if the ref was created
+    pub old: Option<VcsHash>,
+    /// The commit the ref points to after, None if the ref was deleted
+    pub new: Option<VcsHash>,
+    pub time: DateTime<Local>,
+    /// The command that moved the ref, like "commit" or "reset"
+    pub command: String,
+    pub message: String,
+}
+
+/// Append-only logs of HEAD and branch movements, the oldest entry goes first
+#[derive(Deserialize, Serialize, Default)]
+pub struct Reflog {
+    pub refs: BTreeMap<String, Vec<ReflogEntry>>,
+}
+
+impl Reflog {
+    /// Appends an entry for every ref whose commit differs between "old" and "new"
+    ///
+    /// A ref absent in "new", i.e. a deleted branch, gets a deletion entry if it has a log.
+    /// The log is kept so that the commits of the branch can still be found
+    pub fn record(
+        &mut self,
+        old: &BTreeMap<String, VcsHash>,
+        new: &BTreeMap<String, VcsHash>,
+        command: &str,
+        message: &str,
+    ) {
+        let time = Local::now();
+        for (name, hash) in old.iter().filter(|(x, _)| !new.contains_key(*x)) {
+            if let Some(entries) = self.refs.get_mut(name) {
+                entries.push(ReflogEntry {
+                    old: Some(*hash),
+                    new: None,
+                    time,
+                    command: command.to_owned(),
+                    message: message.to_owned(),
+                });
+            }
+        }
+        for (name, hash) in new.iter() {
+            let old_hash = old.get(name);
+            if old_hash == Some(hash) {
+                continue;
+            }
+            self.refs
+                .entry(name.clone())
+                .or_default()
+                .push(ReflogEntry {
+                    old: old_hash.copied(),
+                    new: Some(*hash),
+                    time,
+                    command: command.to_owned(),
+                    message: message.to_owned(),
+                });
+        }
+    }
+
+    /// Finds the commit the ref pointed to "n" movements ago, "ref@{n}" in revisions
+    ///
+    /// A deletion entry refers to the last commit of the deleted ref
+    pub fn nth(&self, name: &str, n: usize) -> Option<VcsHash> {
+        let entries = self.refs.get(name)?;
+        let index = entries.len().checked_sub(n + 1)?;
+        entries[index].new.or(entries[index].old)
+    }
+
+    /// Drops the entries of the given ref or of all refs, returns the number of dropped entries
+    ///
+    /// Commits kept only by the dropped entries are removed by the next gc
+    pub fn expire(&mut self, name: Option<&str>) -> usize {
+        let mut removed = 0;
+        for (_, entries) in self
+            .refs
+            .iter_mut()
+            .filter(|(x, _)| name.is_none_or(|name| name == x.as_str()))
+        {
+            removed += entries.len();
+            entries.clear();
+        }
+        removed
+    }
+
+    /// Lists commits referenced by the entries, they are kept by gc
+    pub fn hashes(&self) -> impl Iterator<Item = VcsHash> + '_ {
+        self.refs
+            .values()
+            .flatten()
+            .flat_map(|x| x.old.into_iter().chain(x.new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let hash = |c: char| -> VcsHash {
+            serde_json::from_str(&format!("\"{}\"", c.to_string().repeat(40))).unwrap()
+        };
+        let refs = |pairs: &[(&str, char)]| -> BTreeMap<String, VcsHash> {
+            pairs
+                .iter()
+                .map(|(name, c)| (name.to_string(), hash(*c)))
+                .collect()
+        };
+        let mut reflog = Reflog::default();
+        reflog.record(
+            &BTreeMap::new(),
+            &refs(&[("HEAD", 'a'), ("master", 'a')]),
+            "init",
+            "Initial commit",
+        );
+        reflog.record(
+            &refs(&[("HEAD", 'a'), ("master", 'a')]),
+            &refs(&[("HEAD", 'b'), ("master", 'b'), ("dev", 'a')]),
+            "commit",
+            "second",
+        );
+        assert_eq!(reflog.refs["HEAD"].len(), 2);
+        assert_eq!(reflog.refs["dev"].len(), 1);
+        assert!(reflog.refs["dev"][0].old.is_none());
+        assert!(reflog.refs["master"][1].old == Some(hash('a')));
+        assert!(reflog.nth("master", 0) == Some(hash('b')));
+        assert!(reflog.nth("master", 1) == Some(hash('a')));
+        assert!(reflog.nth("master", 2).is_none());
+        assert!(reflog.nth("unknown", 0).is_none());
+
+        reflog.record(
+            &refs(&[("HEAD", 'b'), ("master", 'b'), ("dev", 'a')]),
+            &refs(&[("HEAD", 'b'), ("master", 'b')]),
+            "branch",
+            "Deleted branch dev",
+        );
+        assert_eq!(reflog.refs["dev"].len(), 2);
+        assert!(reflog.refs["dev"][1].new.is_none());
+        assert!(reflog.nth("dev", 0) == Some(hash('a')));
+        assert_eq!(reflog.hashes().count(), 8);
+        assert_eq!(reflog.expire(Some("master")), 2);
+        assert_eq!(reflog.expire(None), 4);
+        assert_eq!(reflog.hashes().count(), 0);
+    }
+}
@@ -0,0 +1,256 @@
+use super::commit::{CommitChanges, FileMove};
+use super::diff::{common_subsequence, split_lines};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+
+/// Minimal similarity in percents used unless another threshold is given
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// Files larger than this number of bytes are matched only if they are equal
+pub const MAX_SIMILARITY_SIZE: usize = 1 << 20;
+
+/// Maximal number of (added file, changed file) pairs compared line by line
+pub const MAX_SIMILARITY_PAIRS: usize = 10_000;
+
+/// Parses a percentage from 0 to 100, possibly followed by "%", or "off"
+///
+/// It is the minimal similarity for a file to be considered renamed or copied,
+/// None means the detection is off
+pub fn parse_rename_threshold(value: &str) -> Result<Option<u8>, Error> {
+    if value == "off" {
+        return Ok(None);
+    }
+    match value.trim_end_matches('%').parse::<u8>() {
+        Ok(x) if x <= 100 => Ok(Some(x)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: the rename threshold must be a percentage from 0 to 100 or \"off\"",
+        )),
+    }
+}
+
+/// Similarity of two files in percents
+///
+/// For text files it is the share of lines kept, binary files are either equal or not similar
+pub fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    if old == new {
+        return 100;
+    }
+    match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(old), Ok(new)) => {
+            let old = split_lines(old);
+            let new = split_lines(new);
+            let common = common_subsequence(&old, &new).len();
+            (200 * common / (old.len() + new.len())) as u8
+        }
+        _ => 0,
+    }
+}
+
+/// Number of lines of a text file, None for a binary one
+fn line_count(contents: &[u8]) -> Option<usize> {
+    std::str::from_utf8(contents)
+        .ok()
+        .map(|x| split_lines(x).len())
+}
+
+/// Turns added files similar enough to other files of "old" into renames and copies
+///
+/// "changes" must be found between "old" and "new", both trees must contain at least
+/// the changed files. An added file is a rename if its source is deleted and a copy otherwise.
+/// Unchanged files are sources of exact copies only. The most similar pairs are matched first.
+/// Files over MAX_SIMILARITY_SIZE and all files when there are more than MAX_SIMILARITY_PAIRS
+/// pairs to compare are matched only if they are equal
+pub fn detect_moves(
+    old: &BTreeMap<String, Vec<u8>>,
+    new: &BTreeMap<String, Vec<u8>>,
+    changes: &mut CommitChanges,
+    threshold: u8,
+) {
+    let deleted: HashSet<&String> = changes.deleted.iter().collect();
+    let modified: HashSet<&String> = changes.modified.iter().collect();
+    let mut exact = HashMap::<&[u8], &String>::new();
+    for (rel_path, contents) in old.iter().rev() {
+        if !deleted.contains(rel_path) && !modified.contains(rel_path) {
+            exact.insert(contents, rel_path);
+        }
+    }
+    // the first source goes last to win
+    let sources: Vec<(&String, &[u8], Option<usize>)> = (changes.deleted.iter())
+        .chain(changes.modified.iter())
+        .map(|x| (x, old[x].as_slice(), line_count(&old[x])))
+        .collect();
+    let mut exact_sources = HashMap::<&[u8], &String>::new();
+    for (from, contents, _) in sources.iter().rev() {
+        exact_sources.insert(contents, from);
+    }
+    let compare_all = changes.added.len() * sources.len() <= MAX_SIMILARITY_PAIRS;
+
+    // (similarity, whether the source is kept, source, destination)
+    let mut candidates = vec![];
+    for to in changes.added.iter() {
+        let contents = &new[to];
+        if contents.is_empty() {
+            continue;
+        }
+        let lines = line_count(contents).filter(|_| contents.len() <= MAX_SIMILARITY_SIZE);
+        match lines {
+            Some(lines) if compare_all => {
+                for (from, old_contents, old_lines) in sources.iter() {
+                    let score = match old_lines {
+                        _ if *old_contents == contents.as_slice() => 100,
+                        // the similarity cannot exceed the share of lines the smaller file has
+                        Some(old_lines)
+                            if old_contents.len() <= MAX_SIMILARITY_SIZE
+                                && 200 * lines.min(*old_lines)
+                                    >= threshold as usize * (lines + old_lines) =>
+                        {
+                            similarity(old_contents, contents)
+                        }
+                        _ => continue,
+                    };
+                    if score >= threshold.max(1) {
+                        candidates.push((score, !deleted.contains(from), *from, to));
+                    }
+                }
+            }
+            _ => {
+                if let Some(from) = exact_sources.get(contents.as_slice()) {
+                    candidates.push((100, !deleted.contains(from), *from, to));
+                }
+            }
+        }
+        if let Some(from) = exact.get(contents.as_slice()) {
+            candidates.push((100, true, *from, to));
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then((a.1, a.2, a.3).cmp(&(b.1, b.2, b.3))));
+
+    let mut matched = HashSet::new();
+    let mut renamed_from = HashSet::new();
+    let (mut renamed, mut copied) = (vec![], vec![]);
+    for (similarity, kept, from, to) in candidates {
+        if !matched.insert(to.clone()) {
+            continue;
+        }
+        let file_move = FileMove {
+            from: from.clone(),
+            to: to.clone(),
+            similarity,
+        };
+        if !kept && renamed_from.insert(from.clone()) {
+            renamed.push(file_move);
+        } else {
+            copied.push(file_move);
+        }
+    }
+    changes.added.retain(|x| !matched.contains(x));
+    changes.deleted.retain(|x| !renamed_from.contains(x));
+    renamed.sort_by(|a, b| a.to.cmp(&b.to));
+    copied.sort_by(|a, b| a.to.cmp(&b.to));
+    changes.renamed = renamed;
+    changes.copied = copied;
+}
+
+/// Maps old paths of the files renamed between the trees to their new paths
+pub fn find_renames(
+    old: &BTreeMap<String, Vec<u8>>,
+    new: &BTreeMap<String, Vec<u8>>,
+    threshold: u8,
+) -> BTreeMap<String, String> {
+    let mut changes = CommitChanges::between(old, new);
+    detect_moves(old, new, &mut changes, threshold);
+    changes
+        .renamed
+        .into_iter()
+        .map(|x| (x.from, x.to))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_moves, similarity, MAX_SIMILARITY_PAIRS};
+    use crate::util::commit::{CommitChanges, FileMove};
+    use std::collections::BTreeMap;
+
+    fn tree(files: &[(&str, &str)]) -> BTreeMap<String, Vec<u8>> {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\n"), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nx\n"), 75);
+        assert_eq!(similarity(b"a\n", b"b\n"), 0);
+        assert_eq!(similarity(b"\xff\x00", b"\xff\x01"), 0);
+    }
+
+    #[test]
+    fn test_detect_moves() {
+        let old = tree(&[
+            ("a.txt", "1\n2\n3\n4\n"),
+            ("b.txt", "x\n"),
+            ("c.txt", "keep\n"),
+            ("d.txt", "other\n"),
+        ]);
+        let new = tree(&[
+            ("dir/a.txt", "1\n2\n3\n5\n"),
+            ("c.txt", "keep\n"),
+            ("e.txt", "keep\n"),
+            ("f.txt", "unrelated\n"),
+        ]);
+        let mut changes = CommitChanges::between(&old, &new);
+        detect_moves(&old, &new, &mut changes, 50);
+        assert_eq!(
+            changes.renamed,
+            vec![FileMove {
+                from: "a.txt".to_owned(),
+                to: "dir/a.txt".to_owned(),
+                similarity: 75,
+            }]
+        );
+        assert_eq!(
+            changes.copied,
+            vec![FileMove {
+                from: "c.txt".to_owned(),
+                to: "e.txt".to_owned(),
+                similarity: 100,
+            }]
+        );
+        assert_eq!(changes.added, vec!["f.txt"]);
+        assert_eq!(changes.deleted, vec!["b.txt", "d.txt"]);
+
+        let mut changes = CommitChanges::between(&old, &new);
+        detect_moves(&old, &new, &mut changes, 80);
+        assert!(changes.renamed.is_empty());
+        assert_eq!(changes.added, vec!["dir/a.txt", "f.txt"]);
+    }
+
+    #[test]
+    fn test_detect_moves_limit() {
+        let count = (MAX_SIMILARITY_PAIRS as f64).sqrt() as usize + 1;
+        let old: BTreeMap<String, Vec<u8>> = (0..count)
+            .map(|i| (format!("old{}", i), format!("{}\nx\n", i).into_bytes()))
+            .collect();
+        let mut new: BTreeMap<String, Vec<u8>> = (0..count)
+            .map(|i| (format!("new{}", i), format!("{}\ny\n", i).into_bytes()))
+            .collect();
+        new.insert("new0".to_owned(), old["old0"].clone());
+
+        // too many pairs to compare, only the equal files are matched
+        let mut changes = CommitChanges::between(&old, &new);
+        detect_moves(&old, &new, &mut changes, 50);
+        assert_eq!(changes.renamed.len(), 1);
+        assert_eq!(changes.renamed[0].from, "old0");
+        assert_eq!(changes.renamed[0].to, "new0");
+
+        let old: BTreeMap<String, Vec<u8>> = old.into_iter().take(2).collect();
+        let new: BTreeMap<String, Vec<u8>> = new.into_iter().take(2).collect();
+        let mut changes = CommitChanges::between(&old, &new);
+        detect_moves(&old, &new, &mut changes, 50);
+        assert_eq!(changes.renamed.len(), 2);
+    }
+}
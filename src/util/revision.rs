@@ -0,0 +1,95 @@
+use super::commit::Commit;
+use super::hash::VcsHash;
+use super::vcs_state::VcsState;
+use indoc::indoc;
+use std::io::{Error, ErrorKind};
+
+/// Checks that "name" can be used for a branch or a tag, "kind" names which one it is
+///
+/// The name must be resolvable as a revision, so "HEAD", the reflog form "@{" and
+/// whitespace are not allowed
+pub fn check_ref_name(name: &str, kind: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name == "HEAD"
+        || name.contains("@{")
+        || name.chars().any(|c| c.is_whitespace())
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("error: {} is not a valid {} name", name, kind),
+        ));
+    }
+    Ok(())
+}
+
+/// Finds the only commit whose hash starts with the given prefix
+pub fn find_commit_by_prefix<'a>(state: &'a VcsState, prefix: &str) -> Result<&'a Commit, Error> {
+    if prefix.len() > 40 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: provided hash prefix is longer than 40 symbols",
+        ));
+    }
+    let matching_commits: Vec<&Commit> = state
+        .commits
+        .iter()
+        .filter(|x| prefix[..] == x.hash.to_string()[..prefix.len()])
+        .collect();
+    if matching_commits.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                indoc! {
+                "No commit with hash {} exists.
+                Aborting..."},
+                prefix
+            ),
+        ));
+    }
+    if matching_commits.len() > 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "hash is ambiguous: hash prefix matches several hashes",
+        ));
+    }
+    Ok(matching_commits[0])
+}
+
+/// Resolves a revision to the commit hash
+///
+/// A revision is "HEAD", a branch name, a tag name or a commit hash prefix.
+/// "<ref>@{n}" is the commit HEAD or a branch pointed to "n" movements ago
+pub fn resolve_revision(state: &VcsState, revision: &str) -> Result<VcsHash, Error> {
+    if let Some((name, n)) = parse_reflog_revision(revision) {
+        let name = if name.is_empty() { "HEAD" } else { name };
+        return state.reflog.nth(name, n).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("error: reflog of {} has no entry {}", name, n),
+            )
+        });
+    }
+    if revision == "HEAD" {
+        return Ok(state.head);
+    }
+    if let Some(branch) = state.branches.iter().find(|x| x.name == revision) {
+        return Ok(branch.commit_hash);
+    }
+    if let Some(tag) = state.tags.iter().find(|x| x.name == revision) {
+        return Ok(tag.commit_hash);
+    }
+    if revision.is_empty() || !revision.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("error: unknown revision {}", revision),
+        ));
+    }
+    Ok(find_commit_by_prefix(state, &revision.to_ascii_lowercase())?.hash)
+}
+
+/// Splits a revision like "master@{2}" into the ref name and the number of movements
+fn parse_reflog_revision(revision: &str) -> Option<(&str, usize)> {
+    let (name, rest) = revision.split_once("@{")?;
+    let n = rest.strip_suffix('}')?.parse().ok()?;
+    Some((name, n))
+}
@@ -1,25 +1,34 @@
-use super::archiving::{unzip, zip};
+use super::archiving::{compare_files, read_file, read_files, read_files_matching, unzip, zip};
 use super::commit::CommitChanges;
 use super::hash::VcsHash;
-use std::collections::HashSet;
-use std::fs::{create_dir, remove_dir_all, remove_file};
-use std::io::Error;
-use std::path::{Path, PathBuf};
+use super::rename::detect_moves;
+use indoc::indoc;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{create_dir_all, read, remove_file, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
 use walkdir::WalkDir;
 
 /// Creates a snapshot of the current state of repository
-pub fn create_snapshot(repos_root: &Path) -> Result<VcsHash, Error> {
-    let archive_path = repos_root.join(".vcs").join("snapshots").join("temp.zip");
-    zip(&repos_root, &archive_path)?;
-    let hash = VcsHash::from_file(&archive_path)?;
-    std::fs::rename(
-        &archive_path,
-        // unwrap: "archive_path" is definitely not "/"
-        archive_path
-            .parent()
-            .unwrap()
-            .join(format!("{}.zip", hash).as_str()),
-    )?;
+///
+/// The snapshot is named by the hash of the archive together with "header" describing
+/// its owner, like parents, time and message of a commit. So commits and stash entries
+/// with identical trees get their own snapshots
+pub fn create_snapshot(repos_root: &Path, header: &str) -> Result<VcsHash, Error> {
+    let snapshots_dir = repos_root.join(".vcs").join("snapshots");
+    let archive_path = snapshots_dir.join("temp.zip");
+    zip(repos_root, &archive_path)?;
+    let archive_hash = VcsHash::from_file(&archive_path)?;
+    let hash = VcsHash::from_bytes(format!("{}\n{}", archive_hash, header).as_bytes());
+    let snapshot_path = snapshots_dir.join(format!("{}.zip", hash));
+    if snapshot_path.try_exists()? {
+        remove_file(&archive_path)?;
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("error: snapshot {} already exists", hash),
+        ));
+    }
+    std::fs::rename(&archive_path, snapshot_path)?;
     Ok(hash)
 }
 
@@ -28,9 +37,9 @@ pub fn restore_from_snapshot(repos_root: &Path, commit_hash: &VcsHash) -> Result
     let archive_path = repos_root
         .join(".vcs")
         .join("snapshots")
-        .join(format!("{}.zip", commit_hash.to_string()));
+        .join(format!("{}.zip", commit_hash));
 
-    for path in WalkDir::new(&repos_root)
+    for path in WalkDir::new(repos_root)
         .into_iter()
         .filter_map(|e| e.ok())
         .map(|x| x.path().to_owned())
@@ -49,64 +58,199 @@ pub fn restore_from_snapshot(repos_root: &Path, commit_hash: &VcsHash) -> Result
     Ok(())
 }
 
-/// Compares the repository with the snapshot from commit_hash.
-pub fn find_changes(repos_root: &Path, commit_hash: &VcsHash) -> Result<CommitChanges, Error> {
-    let snapshot_root = repos_root.join(".vcs").join("snapshot_to_compare");
-    create_dir(&snapshot_root)?;
+/// Reads the file at "rel_path" from the snapshot of the given commit
+///
+/// Returns None if the file is absent in the snapshot
+pub fn read_file_from_snapshot(
+    repos_root: &Path,
+    commit_hash: &VcsHash,
+    rel_path: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    let archive_path = repos_root
+        .join(".vcs")
+        .join("snapshots")
+        .join(format!("{}.zip", commit_hash));
+    Ok(read_file(&archive_path, rel_path)?)
+}
+
+/// Reads all files from the snapshot of the given commit
+///
+/// Keys are paths relative to the repository root
+pub fn read_snapshot(
+    repos_root: &Path,
+    commit_hash: &VcsHash,
+) -> Result<BTreeMap<String, Vec<u8>>, Error> {
     let archive_path = repos_root
         .join(".vcs")
         .join("snapshots")
-        .join(format!("{}.zip", commit_hash.to_string()));
-    unzip(&archive_path, &snapshot_root)?;
-    let mut snapshot_paths = HashSet::<PathBuf>::new();
-    for entry in WalkDir::new(&snapshot_root)
+        .join(format!("{}.zip", commit_hash));
+    Ok(read_files(&archive_path)?)
+}
+
+/// Makes the working tree consist of exactly the given files
+///
+/// Keys are paths relative to the repository root. Files with unchanged contents are not rewritten
+pub fn write_tree(repos_root: &Path, files: &BTreeMap<String, Vec<u8>>) -> Result<(), Error> {
+    for rel_path in working_tree_files(repos_root)? {
+        if !files.contains_key(&rel_path) {
+            remove_file(repos_root.join(rel_path))?;
+        }
+    }
+    for (rel_path, contents) in files {
+        let path = repos_root.join(rel_path);
+        if path.is_file() && read(&path)? == *contents {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(&path, contents)?;
+    }
+    Ok(())
+}
+
+/// Lists the files of the working tree relative to the repository root
+pub fn working_tree_files(repos_root: &Path) -> Result<Vec<String>, Error> {
+    working_tree_paths(repos_root, |x| x.is_file())
+}
+
+/// Lists the directories of the working tree relative to the repository root
+pub fn working_tree_dirs(repos_root: &Path) -> Result<Vec<String>, Error> {
+    working_tree_paths(repos_root, |x| x.is_dir())
+}
+
+fn working_tree_paths(
+    repos_root: &Path,
+    keep: impl Fn(&Path) -> bool,
+) -> Result<Vec<String>, Error> {
+    let mut result = vec![];
+    for entry in WalkDir::new(repos_root)
+        .min_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
     {
         let path = entry.path();
-        // unwrap: "temp_path" is always a prefix of "path"
-        let rel_path = path.strip_prefix(&snapshot_root).unwrap().to_owned();
-        if path.is_file() {
-            snapshot_paths.insert(rel_path);
-        }
-    }
-    let mut repos_paths = HashSet::<PathBuf>::new();
-    for entry in WalkDir::new(&repos_root).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        // unwrap: "temp_path" is always a prefix of "path"
-        let rel_path = path.strip_prefix(&repos_root).unwrap();
+        // unwrap: "repos_root" is always a prefix of "path"
+        let rel_path = path.strip_prefix(repos_root).unwrap();
         if rel_path
             .components()
             .any(|x| x.as_os_str().to_str() == Some(".vcs"))
         {
             continue;
         }
-        if path.is_file() {
-            repos_paths.insert(rel_path.to_owned());
+        if keep(path) {
+            // unwrap: os_str is always at least Unicode
+            result.push(rel_path.to_str().unwrap().to_owned());
         }
     }
+    result.sort();
+    Ok(result)
+}
+
+/// Checks whether "rel_path" is one of "paths" or lies inside one of them
+///
+/// Empty "paths" match the whole working tree
+pub fn path_matches(rel_path: &str, paths: &[String]) -> bool {
+    paths.is_empty()
+        || paths.iter().any(|x| {
+            x.is_empty()
+                || rel_path == x
+                || (rel_path.starts_with(x.as_str()) && rel_path[x.len()..].starts_with('/'))
+        })
+}
+
+/// Fails if the working tree differs from the snapshot of the given commit
+///
+/// "action" completes the hint "... before you <action>"
+pub fn ensure_no_changes(
+    repos_root: &Path,
+    commit_hash: &VcsHash,
+    action: &str,
+) -> Result<(), Error> {
+    let changes = find_changes(repos_root, commit_hash)?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut result =
+        "error: Your local changes to the following files should be commited or dropped:\n"
+            .to_owned();
+    for rel_path in changes.paths() {
+        result.push_str(&format!("  {}\n", rel_path));
+    }
+    result.push_str(&format!(
+        indoc! {
+        "Please commit, stash or drop (see restore) your changes before you {}.
+        Aborting..."},
+        action
+    ));
+    Err(Error::other(result))
+}
+
+/// Reads all files of the working tree, keys are paths relative to the repository root
+pub fn read_working_tree(repos_root: &Path) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut result = BTreeMap::new();
+    for rel_path in working_tree_files(repos_root)? {
+        let contents = read(repos_root.join(&rel_path))?;
+        result.insert(rel_path, contents);
+    }
+    Ok(result)
+}
+
+/// Compares the repository with the snapshot from commit_hash.
+///
+/// Files are compared one by one, renames and copies are not detected
+pub fn find_changes(repos_root: &Path, commit_hash: &VcsHash) -> Result<CommitChanges, Error> {
+    let archive_path = repos_root
+        .join(".vcs")
+        .join("snapshots")
+        .join(format!("{}.zip", commit_hash));
+    let compared = compare_files(&archive_path, repos_root)?;
     let mut changes = CommitChanges::default();
-    for rel_path in snapshot_paths {
-        if repos_paths.contains(&rel_path) {
-            let hash_old = VcsHash::from_file(&snapshot_root.join(&rel_path))?;
-            let hash_new = VcsHash::from_file(&repos_root.join(&rel_path))?;
-            if hash_old != hash_new {
-                // unwrap: os_str is always at least Unicode
-                changes.modified.push(rel_path.to_str().unwrap().to_owned());
-            }
+    for (rel_path, same) in compared.iter() {
+        if *same {
+            continue;
+        }
+        if repos_root.join(rel_path).is_file() {
+            changes.modified.push(rel_path.clone());
         } else {
-            // unwrap: os_str is always at least Unicode
-            changes.deleted.push(rel_path.to_str().unwrap().to_owned());
+            changes.deleted.push(rel_path.clone());
         }
-        repos_paths.remove(&rel_path);
     }
-    for rel_path in repos_paths {
-        // unwrap: os_str is always at least Unicode
-        changes.added.push(rel_path.to_str().unwrap().to_owned());
+    changes.added = working_tree_files(repos_root)?
+        .into_iter()
+        .filter(|x| !compared.contains_key(x))
+        .collect();
+    Ok(changes)
+}
+
+/// Compares the repository with the snapshot from commit_hash detecting renamed and copied files
+///
+/// Only the files that may be moved are read. "rename_threshold" is the minimal similarity
+/// in percents, None turns the detection off
+pub fn find_changes_with_moves(
+    repos_root: &Path,
+    commit_hash: &VcsHash,
+    rename_threshold: Option<u8>,
+) -> Result<CommitChanges, Error> {
+    let mut changes = find_changes(repos_root, commit_hash)?;
+    let threshold = match rename_threshold {
+        Some(x) if !changes.added.is_empty() => x,
+        _ => return Ok(changes),
+    };
+    let mut added = BTreeMap::new();
+    for rel_path in changes.added.iter() {
+        added.insert(rel_path.clone(), read(repos_root.join(rel_path))?);
     }
-    changes.modified.sort();
-    changes.added.sort();
-    changes.deleted.sort();
-    remove_dir_all(&snapshot_root)?;
+    let sizes: HashSet<u64> = added.values().map(|x| x.len() as u64).collect();
+    let sources: HashSet<&String> = changes.deleted.iter().chain(&changes.modified).collect();
+    let archive_path = repos_root
+        .join(".vcs")
+        .join("snapshots")
+        .join(format!("{}.zip", commit_hash));
+    // unchanged files can be sources of exact copies only
+    let snapshot = read_files_matching(&archive_path, |name, size| {
+        sources.contains(&name.to_owned()) || sizes.contains(&size)
+    })?;
+    detect_moves(&snapshot, &added, &mut changes, threshold);
     Ok(changes)
 }
@@ -0,0 +1,44 @@
+use super::commit::CommitChanges;
+use super::hash::VcsHash;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Error};
+use std::path::Path;
+
+/// Local changes put aside by "stash push"
+#[derive(Deserialize, Serialize, Clone)]
+pub struct StashEntry {
+    /// Snapshot of the working tree, it is not a commit
+    pub hash: VcsHash,
+    /// The commit the changes were made on
+    pub base: VcsHash,
+    pub branch_name: String,
+    pub time: DateTime<Local>,
+    pub message: String,
+    pub changes: CommitChanges,
+}
+
+/// Stores stashed changes, the newest entry goes first
+#[derive(Deserialize, Serialize, Default)]
+pub struct Stash {
+    pub entries: Vec<StashEntry>,
+}
+
+impl Stash {
+    /// Loads the stash from "<repos_root>/.vcs", it is empty if nothing was stashed yet
+    pub fn load(repos_root: &Path) -> Result<Self, Error> {
+        let path = repos_root.join(".vcs").join("stash.json");
+        if !path.try_exists()? {
+            return Ok(Stash::default());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Updates the stash at "<repos_root>/.vcs/"
+    pub fn update_vcs_dir(&self, repos_root: &Path) -> Result<(), Error> {
+        let file = File::create(repos_root.join(".vcs").join("stash.json"))?;
+        let writer = std::io::BufWriter::new(file);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+}
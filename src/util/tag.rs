@@ -0,0 +1,29 @@
+use super::hash::VcsHash;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Stores a named reference to a commit
+///
+/// Lightweight tags have no annotation
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Tag {
+    pub name: String,
+    pub commit_hash: VcsHash,
+    pub annotation: Option<TagAnnotation>,
+}
+
+/// Extra data of an annotated tag
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TagAnnotation {
+    pub tagger: String,
+    pub time: DateTime<Local>,
+    pub message: String,
+}
+
+/// Name of the current user taken from the environment
+pub fn current_user() -> String {
+    ["VCS_USER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|x| std::env::var(x).ok())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
@@ -1,10 +1,12 @@
 use super::hash::VcsHash;
-use super::{branch::Branch, commit::Commit};
+use super::reflog::Reflog;
+use super::{branch::Branch, commit::Commit, tag::Tag};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env::current_dir;
 use std::fs::{create_dir, File};
 use std::io::{BufReader, Error, ErrorKind};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 /// Contains the full state of the repository
 #[derive(Deserialize, Serialize)]
@@ -13,6 +15,10 @@ pub struct VcsState {
     pub branch_name: String,
     pub branches: Vec<Branch>,
     pub commits: Vec<Commit>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub reflog: Reflog,
 }
 
 /// Finds the closest ancestor directory containing ".vcs" folder
@@ -29,7 +35,48 @@ pub fn find_repos_root() -> Result<PathBuf, Error> {
     ))
 }
 
+/// Converts a path given relative to the current directory into a path relative to "repos_root"
+///
+/// The repository root itself is represented by an empty string
+pub fn relative_to_repos_root(repos_root: &Path, path: &str) -> Result<String, Error> {
+    let mut normalized = PathBuf::new();
+    for component in current_dir()?.join(path).components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            x => normalized.push(x),
+        }
+    }
+    match normalized.strip_prefix(repos_root) {
+        Ok(rel_path) if !rel_path.starts_with(".vcs") => Ok(rel_path
+            .to_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "error: path is not Unicode"))?
+            .to_owned()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("error: path {} is outside the repository", path),
+        )),
+    }
+}
+
 impl VcsState {
+    /// Finds the commit with the given hash
+    pub fn find_commit(&self, hash: &VcsHash) -> Option<&Commit> {
+        self.commits.iter().find(|x| &x.hash == hash)
+    }
+
+    /// Checks whether the current commit is not the last one of the current branch
+    ///
+    /// This is the case after jumping to a historical commit, new commits cannot be made there
+    pub fn is_detached(&self) -> bool {
+        !self
+            .branches
+            .iter()
+            .any(|x| x.name == self.branch_name && x.commit_hash == self.head)
+    }
+
     /// Loads VCS state from "<repos_root>/.vcs"
     pub fn load(repos_root: &Path) -> Result<Self, Error> {
         let file = File::open(repos_root.join(".vcs").join("status.json"))?;
@@ -43,6 +90,35 @@ impl VcsState {
         Ok(serde_json::to_writer_pretty(writer, self)?)
     }
 
+    /// Updates the VCS state at "<repos_root>/.vcs/" logging the moved refs
+    ///
+    /// "command" and "message" describe the movement in the reflog
+    pub fn update_refs(
+        &mut self,
+        repos_root: &Path,
+        command: &str,
+        message: &str,
+    ) -> Result<(), Error> {
+        let old = Self::load(repos_root)?;
+        self.reflog
+            .record(&old.refs(), &self.refs(), command, message);
+        self.update_vcs_dir(repos_root)
+    }
+
+    /// Maps HEAD and branch names to the commits they point to
+    pub fn refs(&self) -> BTreeMap<String, VcsHash> {
+        let mut result: BTreeMap<String, VcsHash> = self
+            .branches
+            .iter()
+            .map(|x| (x.name.clone(), x.commit_hash))
+            .collect();
+        // HEAD is zero before the initial commit is made
+        if self.head != VcsHash::zero() {
+            result.insert("HEAD".to_owned(), self.head);
+        }
+        result
+    }
+
     /// Initializes VCS at "<repos_root>/.vcs" without any commits
     pub fn init(repos_root: &Path) -> Result<VcsState, Error> {
         let path = repos_root.join(".vcs");
@@ -56,6 +132,8 @@ impl VcsState {
             branch_name: "master".to_owned(),
             branches: vec![],
             commits: vec![],
+            tags: vec![],
+            reflog: Reflog::default(),
         };
         serde_json::to_writer_pretty(writer, &state)?;
         Ok(state)
@@ -64,21 +142,43 @@ impl VcsState {
     pub fn assert_validity(repos_path: &Path) {
         use std::collections::{HashMap, HashSet};
 
-        let state = VcsState::load(&repos_path).unwrap();
+        let state = VcsState::load(repos_path).unwrap();
         let commits: HashMap<VcsHash, &Commit> =
             HashMap::from_iter(state.commits.iter().map(|x| (x.hash, x)));
         let branches: HashSet<&String> = HashSet::from_iter(state.branches.iter().map(|x| &x.name));
-        assert!(commits.get(&state.head).is_some());
-        assert_eq!(state.commits[0].parent, VcsHash::zero());
+        assert_eq!(branches.len(), state.branches.len());
+        assert!(branches.contains(&state.branch_name));
+        assert!(commits.contains_key(&state.head));
+        assert!(state.commits[0].parents.is_empty());
         assert!(!state.branches.is_empty());
         assert_eq!(state.branches[0].name, "master");
-        for commit in state.commits {
-            assert!(branches.contains(&commit.branch_name));
+        for tag in state.tags.iter() {
+            assert!(commits.contains_key(&tag.commit_hash));
+        }
+        for hash in state.reflog.hashes() {
+            assert!(commits.contains_key(&hash));
+        }
+        let roots: Vec<VcsHash> = std::iter::once(state.head)
+            .chain(state.branches.iter().map(|x| x.commit_hash))
+            .chain(state.tags.iter().map(|x| x.commit_hash))
+            .chain(state.reflog.hashes())
+            .collect();
+        let reachable = super::graph::ancestors(&state, &roots);
+        for commit in state.commits.iter() {
+            // commits of a deleted branch are kept until gc while its reflog is not expired
+            assert!(
+                branches.contains(&commit.branch_name)
+                    || state.reflog.refs.contains_key(&commit.branch_name)
+                    || reachable.contains(&commit.hash)
+            );
             let snapshot_path = repos_path
                 .join(".vcs")
                 .join("snapshots")
-                .join(format!("{}.zip", commit.hash.to_string()));
+                .join(format!("{}.zip", commit.hash));
             assert!(snapshot_path.try_exists().unwrap());
+            for parent in commit.parents.iter() {
+                assert!(commits.contains_key(parent));
+            }
         }
     }
 }
@@ -0,0 +1,323 @@
+use crate::util::bisect::{midpoint, BisectState};
+use crate::util::merge::ensure_no_merge_in_progress;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::{
+    ensure_no_changes, restore_from_snapshot, working_tree_dirs, working_tree_files,
+};
+use crate::util::vcs_state::VcsState;
+use indoc::indoc;
+use std::fs::{remove_dir, remove_file};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// The verdict on a tested commit
+#[derive(Clone, Copy)]
+enum Mark {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl Mark {
+    fn name(&self) -> &'static str {
+        match self {
+            Mark::Good => "good",
+            Mark::Bad => "bad",
+            Mark::Skip => "skip",
+        }
+    }
+}
+
+/// Starts the search for the commit that introduced a regression
+///
+/// The bad and good revisions can be given here or marked later. Once both are known
+/// the commit in the middle of the range is checked out to be tested
+pub fn start(repos_root: &Path, bad: &Option<String>, good: &[String]) -> Result<String> {
+    let result = start_search(repos_root, bad, good).map(|x| x.0);
+    recorded(repos_root, "bisect start", result)
+}
+
+/// Marks the revision, the current commit by default, as one without the regression
+pub fn good(repos_root: &Path, revision: &Option<String>) -> Result<String> {
+    mark(repos_root, revision, Mark::Good)
+}
+
+/// Marks the revision, the current commit by default, as one with the regression
+pub fn bad(repos_root: &Path, revision: &Option<String>) -> Result<String> {
+    mark(repos_root, revision, Mark::Bad)
+}
+
+/// Marks the revision, the current commit by default, as one that cannot be tested
+pub fn skip(repos_root: &Path, revision: &Option<String>) -> Result<String> {
+    mark(repos_root, revision, Mark::Skip)
+}
+
+/// Finishes the search and returns to the commit and the branch it was started from
+pub fn reset(repos_root: &Path) -> Result<String> {
+    recorded(repos_root, "bisect reset", reset_search(repos_root))
+}
+
+/// Tests the commits with "command" until the first bad one is found
+///
+/// Exit code 0 marks the commit good, 125 skips it and other codes up to 127 mark it bad.
+/// Greater codes and termination by a signal stop the search. Files the command creates
+/// are removed before the next checkout, the output lists them
+pub fn run(repos_root: &Path, command: &[String]) -> Result<String> {
+    let result = run_search(repos_root, command);
+    recorded(
+        repos_root,
+        &format!("bisect run {}", command.join(" ")),
+        result,
+    )
+}
+
+fn mark(repos_root: &Path, revision: &Option<String>, mark: Mark) -> Result<String> {
+    let result = mark_commit(repos_root, revision, mark).map(|x| x.0);
+    let command = match revision {
+        Some(revision) => format!("bisect {} {}", mark.name(), revision),
+        None => format!("bisect {}", mark.name()),
+    };
+    recorded(repos_root, &command, result)
+}
+
+fn load_in_progress(repos_root: &Path) -> Result<BisectState> {
+    BisectState::load(repos_root)?.ok_or_else(|| {
+        Error::other(indoc! {
+        "error: there is no bisect in progress.
+        Use bisect start to begin one."})
+    })
+}
+
+fn start_search(
+    repos_root: &Path,
+    bad: &Option<String>,
+    good: &[String],
+) -> Result<(String, bool)> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    if BisectState::load(repos_root)?.is_some() {
+        return Err(Error::other(indoc! {
+        "error: a bisect is in progress.
+        Use bisect reset to finish it.
+        Aborting..."}));
+    }
+    let state = VcsState::load(repos_root)?;
+    ensure_no_changes(repos_root, &state.head, "bisect")?;
+    let bisect = BisectState {
+        orig_head: state.head,
+        orig_branch: state.branch_name.clone(),
+        bad: bad
+            .as_ref()
+            .map(|x| resolve_revision(&state, x))
+            .transpose()?,
+        good: good
+            .iter()
+            .map(|x| resolve_revision(&state, x))
+            .collect::<Result<Vec<_>>>()?,
+        skipped: vec![],
+    };
+    next_step(repos_root, state, bisect)
+}
+
+fn mark_commit(repos_root: &Path, revision: &Option<String>, mark: Mark) -> Result<(String, bool)> {
+    let mut bisect = load_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    let hash = resolve_revision(&state, revision.as_deref().unwrap_or("HEAD"))?;
+    match mark {
+        Mark::Good => bisect.good.push(hash),
+        Mark::Bad => bisect.bad = Some(hash),
+        Mark::Skip => bisect.skipped.push(hash),
+    }
+    next_step(repos_root, state, bisect)
+}
+
+/// Stores the search and checks out the next commit to test
+///
+/// Returns the report and whether the search is over
+fn next_step(
+    repos_root: &Path,
+    mut state: VcsState,
+    bisect: BisectState,
+) -> Result<(String, bool)> {
+    let bad = match (bisect.bad, bisect.good.is_empty()) {
+        (Some(bad), false) => bad,
+        (bad, no_good) => {
+            bisect.update_vcs_dir(repos_root)?;
+            let waiting = match (bad, no_good) {
+                (None, true) => "Waiting for both good and bad commits",
+                (None, false) => "Waiting for a bad commit",
+                _ => "Waiting for a good commit",
+            };
+            return Ok((waiting.to_owned(), false));
+        }
+    };
+    let candidates = bisect.candidates(&state, &bad);
+    if candidates.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                indoc! {
+                "error: the bad commit {} is an ancestor of a good commit.
+                Aborting..."},
+                bad.short_str()
+            ),
+        ));
+    }
+    bisect.update_vcs_dir(repos_root)?;
+    let untested: Vec<_> = (candidates.iter())
+        .filter(|x| **x != bad && !bisect.skipped.contains(x))
+        .copied()
+        .collect();
+    if untested.is_empty() {
+        let skipped: Vec<_> = (candidates.iter())
+            .filter(|x| bisect.skipped.contains(x))
+            .collect();
+        if skipped.is_empty() {
+            // unwrap: the bad commit is resolved from an existing revision
+            let commit = state.find_commit(&bad).unwrap();
+            let result = format!(
+                "{} is the first bad commit\n{}\nUse bisect reset to return to branch {}",
+                bad, commit.message, bisect.orig_branch
+            );
+            return Ok((result, true));
+        }
+        let mut result = "There are only skipped commits left to test.\n".to_owned();
+        result.push_str("The first bad commit could be any of:\n");
+        for hash in skipped.into_iter().chain(std::iter::once(&bad)) {
+            result.push_str(&format!("  {}\n", hash));
+        }
+        result.pop();
+        return Ok((result, true));
+    }
+
+    // unwrap: "untested" is not empty
+    let (hash, reach) = midpoint(&state, &candidates, &untested).unwrap();
+    if hash != state.head {
+        ensure_no_changes(repos_root, &state.head, "bisect")?;
+        restore_from_snapshot(repos_root, &hash)?;
+        state.head = hash;
+        state.update_refs(
+            repos_root,
+            "bisect",
+            &format!("Checking out {}", hash.short_str()),
+        )?;
+    }
+    // the bad commit is among the candidates left in either case
+    let left = (reach - 1).max(candidates.len() - reach - 1);
+    // unwrap: the midpoint is one of the existing commits
+    let commit = state.find_commit(&hash).unwrap();
+    Ok((
+        format!(
+            "Bisecting: {} revisions left to test after this (roughly {} steps)\n[{}] {}",
+            left,
+            (left + 1).ilog2(),
+            hash.short_str(),
+            commit.message.lines().next().unwrap_or_default()
+        ),
+        false,
+    ))
+}
+
+fn reset_search(repos_root: &Path) -> Result<String> {
+    let bisect = load_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    ensure_no_changes(repos_root, &state.head, "reset the bisect")?;
+    // the branch the search was started from may be deleted already
+    if state.branches.iter().any(|x| x.name == bisect.orig_branch) {
+        state.branch_name = bisect.orig_branch;
+    }
+    restore_from_snapshot(repos_root, &bisect.orig_head)?;
+    state.head = bisect.orig_head;
+    state.update_refs(
+        repos_root,
+        "bisect",
+        &format!("Returning to {}", bisect.orig_head.short_str()),
+    )?;
+    BisectState::remove(repos_root)?;
+    Ok(format!(
+        "Bisect reset. Current commit: {} on branch {}",
+        state.head.short_str(),
+        state.branch_name
+    ))
+}
+
+fn run_search(repos_root: &Path, command: &[String]) -> Result<String> {
+    let bisect = load_in_progress(repos_root)?;
+    if bisect.bad.is_none() || bisect.good.is_empty() {
+        return Err(Error::other(
+            "error: bisect run needs both a good and a bad commit",
+        ));
+    }
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "error: the command is empty"))?;
+    let mut result = String::new();
+    loop {
+        let head = VcsState::load(repos_root)?.head;
+        let files = working_tree_files(repos_root)?;
+        let dirs = working_tree_dirs(repos_root)?;
+        let status = Command::new(program)
+            .args(args)
+            .current_dir(repos_root)
+            .status()?;
+        let mark = match status.code() {
+            Some(0) => Mark::Good,
+            Some(125) => Mark::Skip,
+            Some(1..=127) => Mark::Bad,
+            _ => {
+                return Err(Error::other(format!(
+                    indoc! {
+                    "The command exited with {} at commit {}, bisect run cannot continue.
+                    Aborting..."},
+                    status,
+                    head.short_str()
+                )))
+            }
+        };
+        result.push_str(&remove_created(repos_root, &files, &dirs)?);
+        let (output, finished) = mark_commit(repos_root, &None, mark)?;
+        result.push_str(&format!(
+            "{} is {}\n{}\n",
+            head.short_str(),
+            mark.name(),
+            output
+        ));
+        if finished {
+            break;
+        }
+    }
+    result.pop();
+    Ok(result)
+}
+
+/// Removes files and directories absent in "files" and "dirs" and reports them
+///
+/// They are made by the command of bisect run, like build outputs or logs,
+/// and would block the checkout of the next commit to test
+fn remove_created(repos_root: &Path, files: &[String], dirs: &[String]) -> Result<String> {
+    let mut removed = vec![];
+    for rel_path in working_tree_files(repos_root)? {
+        if files.binary_search(&rel_path).is_err() {
+            remove_file(repos_root.join(&rel_path))?;
+            removed.push(rel_path);
+        }
+    }
+    // the deepest directories go first so that they are empty when removed
+    for rel_path in working_tree_dirs(repos_root)?.into_iter().rev() {
+        if dirs.binary_search(&rel_path).is_err() {
+            remove_dir(repos_root.join(&rel_path))?;
+            removed.push(format!("{}/", rel_path));
+        }
+    }
+    if removed.is_empty() {
+        return Ok(String::new());
+    }
+    let mut result = "Removed files made by the command:\n".to_owned();
+    for rel_path in removed {
+        result.push_str(&format!("  {}\n", rel_path));
+    }
+    Ok(result)
+}
@@ -0,0 +1,220 @@
+use crate::util::diff::{common_subsequence, split_lines};
+use crate::util::hash::VcsHash;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::read_file_from_snapshot;
+use crate::util::vcs_state::VcsState;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Consecutive lines of the file introduced by the same commit
+struct BlameBlock {
+    hash: VcsHash,
+    /// 0-based index of the first line in the commit that introduced it
+    orig_start: usize,
+    /// 0-based index of the first line in the blamed revision
+    final_start: usize,
+    len: usize,
+}
+
+/// Shows the commit that introduced each line of the file
+///
+/// "lines" is a 1-based inclusive range
+pub fn run(
+    repos_root: &Path,
+    path: &str,
+    revision: &Option<String>,
+    lines: &Option<(usize, usize)>,
+) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let mut blocks = Vec::<BlameBlock>::new();
+    let Some((contents, end)) = find_blocks(&state, repos_root, path, revision, lines, |x| {
+        blocks.push(x);
+        Ok(())
+    })?
+    else {
+        return Ok(String::new());
+    };
+
+    let final_lines = split_lines(&contents);
+    let mut result = String::new();
+    blocks.sort_by_key(|x| x.final_start);
+    let branch_width = blocks
+        .iter()
+        // unwrap: assume the state.commits is valid
+        .map(|x| state.find_commit(&x.hash).unwrap().branch_name.len())
+        .max()
+        .unwrap_or(0);
+    let number_width = end.to_string().len();
+    for block in blocks.iter() {
+        // unwrap: assume the state.commits is valid
+        let commit = state.find_commit(&block.hash).unwrap();
+        for (i, line) in final_lines
+            .iter()
+            .enumerate()
+            .skip(block.final_start)
+            .take(block.len)
+        {
+            result.push_str(&format!(
+                "{} ({:<branch_width$} {} {:>number_width$}) {}\n",
+                commit.hash.short_str(),
+                commit.branch_name,
+                commit.time.format("%Y-%m-%d %X %z"),
+                i + 1,
+                line.trim_end_matches('\n'),
+            ));
+        }
+    }
+    result.pop();
+    Ok(result)
+}
+
+/// Writes blocks of lines to "out" in machine-readable form as soon as the commit
+/// that introduced them is found
+///
+/// "lines" is a 1-based inclusive range
+pub fn incremental(
+    repos_root: &Path,
+    path: &str,
+    revision: &Option<String>,
+    lines: &Option<(usize, usize)>,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    let state = VcsState::load(repos_root)?;
+    let mut described = HashSet::<VcsHash>::new();
+    find_blocks(&state, repos_root, path, revision, lines, |block| {
+        writeln!(
+            out,
+            "{} {} {} {}",
+            block.hash,
+            block.orig_start + 1,
+            block.final_start + 1,
+            block.len
+        )?;
+        if described.insert(block.hash) {
+            // unwrap: assume the state.commits is valid
+            let commit = state.find_commit(&block.hash).unwrap();
+            writeln!(out, "branch {}", commit.branch_name)?;
+            writeln!(out, "date {}", commit.time.format("%Y-%m-%d %X %z"))?;
+            writeln!(out, "summary {}", commit.message)?;
+        }
+        writeln!(out, "filename {}", path)?;
+        out.flush()
+    })?;
+    Ok(())
+}
+
+/// Passes blocks of the lines in "lines" range to "on_block" in the order they are attributed
+///
+/// Returns contents of the blamed file and the last line number of the range,
+/// None if the file is empty and the range is not given
+fn find_blocks(
+    state: &VcsState,
+    repos_root: &Path,
+    path: &str,
+    revision: &Option<String>,
+    lines: &Option<(usize, usize)>,
+    mut on_block: impl FnMut(BlameBlock) -> Result<(), Error>,
+) -> Result<Option<(String, usize)>, Error> {
+    let hash = resolve_revision(state, revision.as_deref().unwrap_or("HEAD"))?;
+    let contents = match read_file_from_snapshot(repos_root, &hash, path)? {
+        Some(x) => String::from_utf8_lossy(&x).into_owned(),
+        None => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("error: no file {} in commit {}", path, hash.short_str()),
+            ))
+        }
+    };
+    let final_lines = split_lines(&contents);
+    let (start, end) = lines.unwrap_or((1, final_lines.len()));
+    if final_lines.is_empty() && lines.is_none() {
+        return Ok(None);
+    }
+    if start == 0 || start > end || end > final_lines.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "error: line range {},{} is out of file with {} lines",
+                start,
+                end,
+                final_lines.len()
+            ),
+        ));
+    }
+
+    // lines waiting to be attributed grouped by the commit they are tracked in:
+    // file contents in the commit and pairs (line index in the blamed revision, line index in the commit)
+    let mut pending = HashMap::<VcsHash, (String, Vec<(usize, usize)>)>::new();
+    pending.insert(
+        hash,
+        (contents.clone(), (start - 1..end).map(|i| (i, i)).collect()),
+    );
+    // the latest commit goes first so that all lines reach a commit before it is examined
+    while let Some(hash) = pending
+        .keys()
+        // unwrap: assume the state.commits is valid
+        .max_by_key(|x| state.find_commit(x).unwrap().time)
+        .copied()
+    {
+        // unwrap: the key is taken from the map
+        let (current_contents, mut lines) = pending.remove(&hash).unwrap();
+        // unwrap: assume the state.commits is valid
+        let commit = state.find_commit(&hash).unwrap();
+        let current_lines = split_lines(&current_contents);
+        // a line is passed to the first parent that contains it
+        for parent in commit.parents.iter() {
+            let parent_contents = match read_file_from_snapshot(repos_root, parent, path)? {
+                Some(x) => String::from_utf8_lossy(&x).into_owned(),
+                None => continue,
+            };
+            let parent_index: HashMap<usize, usize> =
+                common_subsequence(&split_lines(&parent_contents), &current_lines)
+                    .into_iter()
+                    .map(|(old, new)| (new, old))
+                    .collect();
+            let mut passed = vec![];
+            lines.retain(|&(final_idx, idx)| match parent_index.get(&idx) {
+                Some(&parent_idx) => {
+                    passed.push((final_idx, parent_idx));
+                    false
+                }
+                None => true,
+            });
+            if !passed.is_empty() {
+                pending
+                    .entry(*parent)
+                    .or_insert_with(|| (parent_contents, vec![]))
+                    .1
+                    .extend(passed);
+            }
+        }
+        lines.sort();
+        let mut block: Option<BlameBlock> = None;
+        for (final_idx, idx) in lines {
+            match block.as_mut() {
+                Some(block)
+                    if block.final_start + block.len == final_idx
+                        && block.orig_start + block.len == idx =>
+                {
+                    block.len += 1;
+                }
+                _ => {
+                    if let Some(block) = block.take() {
+                        on_block(block)?;
+                    }
+                    block = Some(BlameBlock {
+                        hash,
+                        orig_start: idx,
+                        final_start: final_idx,
+                        len: 1,
+                    });
+                }
+            }
+        }
+        if let Some(block) = block {
+            on_block(block)?;
+        }
+    }
+    Ok(Some((contents, end)))
+}
@@ -0,0 +1,174 @@
+use crate::util::graph::ancestors;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::check_ref_name;
+use crate::util::stash::Stash;
+use crate::util::vcs_state::VcsState;
+use indoc::indoc;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+fn check_branch_exists(state: &VcsState, name: &str) -> Result<(), Error> {
+    if state.branches.iter().any(|x| x.name == name) {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                indoc! {"No branch {} exists.
+                Aborting..."},
+                name
+            ),
+        ))
+    }
+}
+
+/// Lists branches with their last commits, the current branch is marked with "*"
+pub fn list(repos_root: &Path) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let width = state
+        .branches
+        .iter()
+        .map(|x| x.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut result = String::new();
+    for branch in state.branches.iter() {
+        // unwrap: assume the state.commits is valid
+        let commit = state.find_commit(&branch.commit_hash).unwrap();
+        result.push_str(&format!(
+            "{} {:<width$} {} {}\n",
+            if branch.name == state.branch_name {
+                '*'
+            } else {
+                ' '
+            },
+            branch.name,
+            branch.commit_hash.short_str(),
+            commit.message
+        ));
+        if let Some(description) = &branch.description {
+            result.push_str(&format!("  {:<width$} {}\n", "", description));
+        }
+    }
+    result.pop();
+    Ok(result)
+}
+
+/// Deletes the branch
+///
+/// Unless "force" is set the branch must be fully merged into the current commit.
+/// Commits of the deleted branch are kept until gc
+pub fn delete(repos_root: &Path, name: &str, force: bool) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    check_branch_exists(&state, name)?;
+    // branches[0] is always master branch
+    if name == state.branches[0].name {
+        return Err(Error::other("error: master branch cannot be deleted"));
+    }
+    if name == state.branch_name {
+        return Err(Error::other(format!(
+            indoc! {
+            "Cannot delete branch {} you are currently on.
+            Aborting..."},
+            name
+        )));
+    }
+    // unwrap: existence is checked above
+    let pos = state.branches.iter().position(|x| x.name == name).unwrap();
+    let hash = state.branches[pos].commit_hash;
+    if !force && !ancestors(&state, &[state.head]).contains(&hash) {
+        return Err(Error::other(format!(
+            indoc! {
+            "Branch {} is not fully merged.
+            Use -D to delete it anyway.
+            Aborting..."},
+            name
+        )));
+    }
+    state.branches.remove(pos);
+    state.update_refs(repos_root, "branch", &format!("Deleted branch {}", name))?;
+    let result = format!("Deleted branch {} (was {})", name, hash.short_str());
+    let command = format!("branch {} {}", if force { "-D" } else { "-d" }, name);
+    recorded(repos_root, &command, Ok(result))
+}
+
+/// Renames the branch, the current one if "old_name" is not provided
+pub fn rename(repos_root: &Path, old_name: Option<&str>, new_name: &str) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    let old_name = old_name.unwrap_or(&state.branch_name).to_owned();
+    check_branch_exists(&state, &old_name)?;
+    if old_name == state.branches[0].name {
+        return Err(Error::other("error: master branch cannot be renamed"));
+    }
+    check_ref_name(new_name, "branch")?;
+    if state.branches.iter().any(|x| x.name == new_name) {
+        return Err(Error::other(format!(
+            indoc! {
+            "Branch {} already exists.
+            Aborting..."},
+            new_name
+        )));
+    }
+    for branch in state.branches.iter_mut().filter(|x| x.name == old_name) {
+        branch.name = new_name.to_owned();
+    }
+    for commit in state
+        .commits
+        .iter_mut()
+        .filter(|x| x.branch_name == old_name)
+    {
+        commit.branch_name = new_name.to_owned();
+    }
+    if state.branch_name == old_name {
+        state.branch_name = new_name.to_owned();
+    }
+    let mut stash = Stash::load(repos_root)?;
+    for entry in stash
+        .entries
+        .iter_mut()
+        .filter(|x| x.branch_name == old_name)
+    {
+        entry.branch_name = new_name.to_owned();
+    }
+    stash.update_vcs_dir(repos_root)?;
+    // the log moves along with the branch, the renaming is logged as its creation.
+    // A deleted branch with the new name keeps its entries, the moved ones follow them
+    if let Some(entries) = state.reflog.refs.remove(&old_name) {
+        state
+            .reflog
+            .refs
+            .entry(new_name.to_owned())
+            .or_default()
+            .extend(entries);
+    }
+    let message = format!("Renamed branch {} to {}", old_name, new_name);
+    state.update_refs(repos_root, "branch", &message)?;
+    recorded(
+        repos_root,
+        &format!("branch -m {} {}", old_name, new_name),
+        Ok(message),
+    )
+}
+
+/// Sets the description of the branch, the current one if "name" is not provided
+///
+/// An empty description removes it
+pub fn describe(repos_root: &Path, name: Option<&str>, description: &str) -> Result<String, Error> {
+    let mut state = VcsState::load(repos_root)?;
+    let name = name.unwrap_or(&state.branch_name).to_owned();
+    check_branch_exists(&state, &name)?;
+    // unwrap: existence is checked above
+    let branch = state.branches.iter_mut().find(|x| x.name == name).unwrap();
+    let result = if description.is_empty() {
+        branch.description = None;
+        format!("Removed description of branch {}", name)
+    } else {
+        branch.description = Some(description.to_owned());
+        format!("Updated description of branch {}", name)
+    };
+    state.update_vcs_dir(repos_root)?;
+    Ok(result)
+}
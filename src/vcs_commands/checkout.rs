@@ -0,0 +1,56 @@
+use crate::util::merge::MergeState;
+use crate::util::snapshot::read_snapshot;
+use std::fs::{create_dir_all, remove_file, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Takes one whole side of the conflicting files during a merge stopped because of conflicts
+///
+/// "ours" is the current commit, "theirs" is the merged, squashed or picked one.
+/// A file absent on the chosen side is deleted. The files are marked as resolved
+pub fn run(repos_root: &Path, paths: &[String], theirs: bool) -> Result<String, Error> {
+    let mut merge_state = match MergeState::load(repos_root)? {
+        Some(x) => x,
+        None => return Err(Error::other("error: there is no merge in progress")),
+    };
+    let (side, hash) = if theirs {
+        // merges stored without "theirs" have the merged commit first
+        match merge_state.theirs.or(merge_state.merged.first().copied()) {
+            Some(hash) => ("theirs", hash),
+            None => {
+                return Err(Error::other(
+                    "error: there is no commit to take theirs from",
+                ))
+            }
+        }
+    } else {
+        ("ours", merge_state.head)
+    };
+    let snapshot = read_snapshot(repos_root, &hash)?;
+
+    let selected = merge_state.conflicts.mark_resolved(paths);
+    if selected.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "error: the paths did not match any conflicting file",
+        ));
+    }
+    let mut result = String::new();
+    for rel_path in selected.iter() {
+        let path = repos_root.join(rel_path);
+        match snapshot.get(rel_path) {
+            Some(contents) => {
+                if let Some(parent) = path.parent() {
+                    create_dir_all(parent)?;
+                }
+                write(&path, contents)?;
+            }
+            None if path.is_file() => remove_file(&path)?,
+            None => {}
+        }
+        result.push_str(&format!("Took {} version of {}\n", side, rel_path));
+    }
+    merge_state.update_vcs_dir(repos_root)?;
+    result.pop();
+    Ok(result)
+}
@@ -0,0 +1,209 @@
+use super::commit::commit_with_parents;
+use crate::util::diff::{ConflictStyle, Favor};
+use crate::util::hash::VcsHash;
+use crate::util::merge::{
+    ensure_no_merge_in_progress, merge_trees, Conflicts, MergeKind, MergeState,
+};
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::{ensure_no_changes, find_changes, read_snapshot, write_tree};
+use crate::util::vcs_state::VcsState;
+use indoc::indoc;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Whether the change of a commit is applied or undone
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    Apply,
+    Revert,
+}
+
+impl Pick {
+    fn command(&self) -> &'static str {
+        self.kind().command()
+    }
+
+    /// The kind of the merge state left by a stopped cherry-pick or revert
+    pub fn kind(&self) -> MergeKind {
+        match self {
+            Pick::Apply => MergeKind::CherryPick,
+            Pick::Revert => MergeKind::Revert,
+        }
+    }
+}
+
+/// Applies the changes the commits introduced relative to their parents onto HEAD as new commits
+pub fn run(
+    repos_root: &Path,
+    revisions: &[String],
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    apply(repos_root, revisions, Pick::Apply, rename_threshold)
+}
+
+/// Applies or undoes the changes of the commits one by one with a three-way merge
+///
+/// A commit with conflicts stops the sequence, it is finished like a merge with
+/// "--continue", which then applies the rest of the commits, or dropped with "--abort"
+pub fn apply(
+    repos_root: &Path,
+    revisions: &[String],
+    pick: Pick,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let result = apply_commits(repos_root, revisions, pick, rename_threshold);
+    let command = format!("{} {}", pick.command(), revisions.join(" "));
+    recorded(repos_root, &command, result)
+}
+
+fn apply_commits(
+    repos_root: &Path,
+    revisions: &[String],
+    pick: Pick,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    if !state
+        .branches
+        .iter()
+        .any(|x| x.name == state.branch_name && x.commit_hash == state.head)
+    {
+        return Err(Error::other(format!(
+            indoc! {
+            "The {} is possible only when you are in the last commit of the branch.
+            Aborting..."},
+            pick.command()
+        )));
+    }
+    let mut hashes = vec![];
+    for revision in revisions {
+        let hash = resolve_revision(&state, revision)?;
+        // unwrap: the revision is resolved to an existing commit
+        if state.find_commit(&hash).unwrap().parents.len() > 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "error: commit {} is a merge commit, it cannot be used with {}",
+                    hash.short_str(),
+                    pick.command()
+                ),
+            ));
+        }
+        hashes.push(hash);
+    }
+    ensure_no_changes(repos_root, &state.head, pick.command())?;
+    pick_commits(repos_root, &hashes, pick, rename_threshold)
+}
+
+/// Applies or undoes the commits one by one on top of HEAD, the working tree must be clean
+///
+/// On conflicts the merge state keeps the commits left to apply after it is finished
+pub fn pick_commits(
+    repos_root: &Path,
+    hashes: &[VcsHash],
+    pick: Pick,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let mut result = String::new();
+    for (i, hash) in hashes.iter().enumerate() {
+        let state = VcsState::load(repos_root)?;
+        // unwrap: the commit is found above
+        let commit = state.find_commit(hash).unwrap().clone();
+        let parent = if commit.parents.is_empty() {
+            BTreeMap::new()
+        } else {
+            read_snapshot(repos_root, &commit.first_parent())?
+        };
+        let target = read_snapshot(repos_root, hash)?;
+        let (base, theirs, message) = match pick {
+            Pick::Apply => (parent, target, commit.message.clone()),
+            Pick::Revert => (
+                target,
+                parent,
+                format!(
+                    "Revert \"{}\"\n\nThis reverts commit {}.",
+                    commit.message, commit.hash
+                ),
+            ),
+        };
+        let tree_merge = merge_trees(
+            &base,
+            &read_snapshot(repos_root, &state.head)?,
+            &theirs,
+            (
+                "HEAD",
+                &format!("{} ({})", hash.short_str(), commit.message),
+            ),
+            ConflictStyle::Merge,
+            Favor::None,
+            rename_threshold,
+        );
+        write_tree(repos_root, &tree_merge.files)?;
+
+        if !tree_merge.conflicts.is_empty() {
+            result.push_str(&format!(
+                "Could not {} {} {}\n",
+                pick.command(),
+                hash.short_str(),
+                commit.message
+            ));
+            for rel_path in tree_merge.conflicts.iter() {
+                result.push_str(&format!("  {}\n", rel_path));
+            }
+            result.push_str(&format!(
+                indoc! {
+                "Conflicts are marked in the files. Resolve them and run {0} --continue,
+                or drop the changes with {0} --abort."},
+                pick.command()
+            ));
+            if i + 1 < hashes.len() {
+                result.push_str(&format!(
+                    "\nApplied after {} --continue: {}",
+                    pick.command(),
+                    hashes[i + 1..]
+                        .iter()
+                        .map(|x| x.short_str())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ));
+            }
+            MergeState {
+                head: state.head,
+                merged: vec![],
+                theirs: match pick {
+                    Pick::Apply => Some(*hash),
+                    Pick::Revert => commit.parents.first().copied(),
+                },
+                message,
+                conflicts: Conflicts::from(&tree_merge),
+                remaining: hashes[i + 1..].to_vec(),
+                kind: pick.kind(),
+            }
+            .update_vcs_dir(repos_root)?;
+            return Ok(result);
+        }
+        if find_changes(repos_root, &state.head)?.is_empty() {
+            result.push_str(&format!(
+                "Skipped {} {}: nothing to commit\n",
+                hash.short_str(),
+                commit.message
+            ));
+            continue;
+        }
+        result.push_str(&commit_with_parents(
+            repos_root,
+            &message,
+            &[],
+            pick.command(),
+            rename_threshold,
+        )?);
+        result.push('\n');
+    }
+    result.pop();
+    Ok(result)
+}
@@ -1,14 +1,101 @@
-use crate::util::commit::Commit;
-use crate::util::snapshot::{create_snapshot, find_changes};
+use super::new_branch;
+use crate::util::commit::{Commit, CommitChanges};
+use crate::util::hash::VcsHash;
+use crate::util::merge::ensure_no_merge_in_progress;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::snapshot::{
+    create_snapshot, find_changes, find_changes_with_moves, working_tree_files,
+};
 use crate::util::vcs_state::VcsState;
 use chrono::Local;
 use indoc::indoc;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::path::Path;
 
 /// Commits changes in the working tree if any
-pub fn run(repos_root: &Path, message: &str) -> Result<String, Error> {
-    let mut state = VcsState::load(&repos_root)?;
+///
+/// Files are recorded as renamed or copied if they are similar enough, see "rename_threshold"
+/// of find_changes_with_moves
+pub fn run(
+    repos_root: &Path,
+    message: &str,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let result = commit_with_parents(repos_root, message, &[], "commit", rename_threshold);
+    recorded(
+        repos_root,
+        &format!("commit -m \"{}\"", title(message)),
+        result,
+    )
+}
+
+/// Starts a new branch from the current commit and commits changes in the working tree there
+///
+/// This way a commit can be made on top of a historical commit
+pub fn on_new_branch(
+    repos_root: &Path,
+    message: &str,
+    name: &str,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    ensure_changes(repos_root)?;
+    let mut result = new_branch::create(repos_root, name, &None)?;
+    result.push('\n');
+    result.push_str(&commit_with_parents(
+        repos_root,
+        message,
+        &[],
+        "commit",
+        rename_threshold,
+    )?);
+    let command = format!("commit --branch {} -m \"{}\"", name, title(message));
+    recorded(repos_root, &command, Ok(result))
+}
+
+/// Fails if there is nothing to commit in the working tree
+fn ensure_changes(repos_root: &Path) -> Result<(), Error> {
+    let state = VcsState::load(repos_root)?;
+    if find_changes(repos_root, &state.head)?.is_empty() {
+        return Err(Error::other("No changes to be committed".to_owned()));
+    }
+    Ok(())
+}
+
+/// Replaces the last commit of the branch with the working tree, the message is kept if not provided
+pub fn amend(
+    repos_root: &Path,
+    message: &Option<String>,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    // unwrap: assume the state.commits is valid
+    let old = state.find_commit(&state.head).unwrap();
+    let message = message.as_ref().unwrap_or(&old.message);
+    let mut result = amend_head(repos_root, message, "commit --amend", rename_threshold)?;
+    result.push_str(&format!("\nReplaced commit {}", old.hash.short_str()));
+    let command = format!("commit --amend -m \"{}\"", title(message));
+    recorded(repos_root, &command, Ok(result))
+}
+
+/// Commits the working tree with "merged" commits as additional parents
+///
+/// A commit with additional parents is created even if there are no changes.
+/// "command" is the one logged in the reflog
+pub fn commit_with_parents(
+    repos_root: &Path,
+    message: &str,
+    merged: &[VcsHash],
+    command: &str,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    let mut state = VcsState::load(repos_root)?;
     // unwrap: assume the state.branch_name is valid
     let branch = state
         .branches
@@ -16,24 +103,114 @@ pub fn run(repos_root: &Path, message: &str) -> Result<String, Error> {
         .find(|branch| branch.name == state.branch_name)
         .unwrap();
     if branch.commit_hash != state.head {
-        return Err(Error::new(
-            ErrorKind::Other,
+        return Err(Error::other(format!(
             indoc! {
-            "You can create a new commit only from last one of the branch.
+            "HEAD is detached at {}, you can create a new commit only from last one of the branch.
+            Use new-branch or commit --branch to start a branch from the current commit.
+            Aborting.."},
+            state.head.short_str()
+        )));
+    }
+    let changes = find_changes_with_moves(repos_root, &state.head, rename_threshold)?;
+    if changes.is_empty() && merged.is_empty() {
+        return Err(Error::other("No changes to be committed".to_owned()));
+    }
+    let parents: Vec<VcsHash> = std::iter::once(state.head)
+        .chain(merged.iter().copied())
+        .collect();
+    let time = Local::now();
+    let hash = create_snapshot(
+        repos_root,
+        &Commit::snapshot_header(&parents, &time, message),
+    )?;
+
+    let mut result = format!("[{} {}] {}\n", state.branch_name, hash.short_str(), message);
+    result.push_str(&changes_summary(&changes));
+    result.pop();
+
+    branch.commit_hash = hash;
+    let commit = Commit {
+        branch_name: branch.name.clone(),
+        time,
+        message: message.to_owned(),
+        changes,
+        hash,
+        parents,
+    };
+    state.head = hash;
+    state.commits.push(commit);
+    state.update_refs(repos_root, command, title(message))?;
+    Ok(result)
+}
+
+/// Replaces the current commit with the working tree and "message" keeping its parents
+///
+/// The replaced commit stays reachable from the reflog. The initial commit has no parents,
+/// all files of the working tree are added by it
+pub fn amend_head(
+    repos_root: &Path,
+    message: &str,
+    command: &str,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    let mut state = VcsState::load(repos_root)?;
+    if state.is_detached() {
+        return Err(Error::other(
+            indoc! {
+            "You can amend only the last commit of the branch.
             Aborting.."}
             .to_owned(),
         ));
     }
-    let changes = find_changes(&repos_root, &state.head)?;
-    if changes.is_empty() {
-        return Err(Error::new(
-            ErrorKind::Other,
-            "No changes to be committed".to_owned(),
-        ));
-    }
-    let hash = create_snapshot(&repos_root)?;
+    // unwrap: assume the state.commits is valid
+    let old = state.find_commit(&state.head).unwrap().clone();
+    let changes = if old.parents.is_empty() {
+        CommitChanges {
+            added: working_tree_files(repos_root)?,
+            ..CommitChanges::default()
+        }
+    } else {
+        find_changes_with_moves(repos_root, &old.first_parent(), rename_threshold)?
+    };
+    let time = Local::now();
+    let hash = create_snapshot(
+        repos_root,
+        &Commit::snapshot_header(&old.parents, &time, message),
+    )?;
 
     let mut result = format!("[{} {}] {}\n", state.branch_name, hash.short_str(), message);
+    result.push_str(&changes_summary(&changes));
+    result.pop();
+
+    for branch in state
+        .branches
+        .iter_mut()
+        .filter(|branch| branch.name == state.branch_name)
+    {
+        branch.commit_hash = hash;
+    }
+    let commit = Commit {
+        branch_name: state.branch_name.clone(),
+        time,
+        message: message.to_owned(),
+        changes,
+        hash,
+        parents: old.parents,
+    };
+    state.head = hash;
+    state.commits.push(commit);
+    state.update_refs(repos_root, command, title(message))?;
+    Ok(result)
+}
+
+/// The first line of the commit message
+fn title(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+/// Describes the changes as a row like "3 files changed, 1 added" followed by the changed files
+fn changes_summary(changes: &CommitChanges) -> String {
+    let mut summary = String::new();
     // constructing a row like: "3 files changed, 1 added"
     {
         let mut to_join = vec![];
@@ -46,8 +223,13 @@ pub fn run(repos_root: &Path, message: &str) -> Result<String, Error> {
         if !changes.deleted.is_empty() {
             to_join.push((changes.deleted.len(), "deleted"));
         }
+        if !changes.renamed.is_empty() {
+            to_join.push((changes.renamed.len(), "renamed"));
+        }
+        if !changes.copied.is_empty() {
+            to_join.push((changes.copied.len(), "copied"));
+        }
         let row: String = (0..to_join.len())
-            .into_iter()
             .map(|i| {
                 if i == 0 {
                     format!(
@@ -61,30 +243,32 @@ pub fn run(repos_root: &Path, message: &str) -> Result<String, Error> {
                 }
             })
             .collect();
-        result.extend(format!("{}\n", row).chars());
+        if row.is_empty() {
+            summary.push_str("  No changes\n");
+        } else {
+            summary.extend(format!("{}\n", row).chars());
+        }
     }
     for file in changes.modified.iter() {
-        result.extend(format!("  modified {file}\n").chars());
+        summary.extend(format!("  modified {file}\n").chars());
     }
     for file in changes.added.iter() {
-        result.extend(format!("  added {file}\n").chars());
+        summary.extend(format!("  added {file}\n").chars());
     }
     for file in changes.deleted.iter() {
-        result.extend(format!("  deleted {file}\n").chars());
+        summary.extend(format!("  deleted {file}\n").chars());
     }
-    result.pop();
-
-    branch.commit_hash = hash;
-    let commit = Commit {
-        branch_name: branch.name.clone(),
-        time: Local::now(),
-        message: message.to_owned(),
-        changes,
-        hash,
-        parent: state.head,
-    };
-    state.head = hash;
-    state.commits.push(commit);
-    state.update_vcs_dir(&repos_root)?;
-    Ok(result)
+    for file_move in changes.renamed.iter() {
+        summary.push_str(&format!(
+            "  renamed {} -> {} ({}%)\n",
+            file_move.from, file_move.to, file_move.similarity
+        ));
+    }
+    for file_move in changes.copied.iter() {
+        summary.push_str(&format!(
+            "  copied {} -> {} ({}%)\n",
+            file_move.from, file_move.to, file_move.similarity
+        ));
+    }
+    summary
 }
@@ -0,0 +1,62 @@
+use crate::util::bisect::BisectState;
+use crate::util::graph::ancestors;
+use crate::util::hash::VcsHash;
+use crate::util::op_log::OpLog;
+use crate::util::rebase::RebaseState;
+use crate::util::stash::Stash;
+use crate::util::vcs_state::VcsState;
+use std::collections::HashSet;
+use std::fs::{read_dir, remove_file};
+use std::io::Error;
+use std::path::Path;
+
+/// Removes commits unreachable from HEAD, branches, tags, stash entries, the reflog,
+/// a rebase and a bisect in progress
+///
+/// Snapshots that belong to neither a kept commit nor a stash entry are removed too
+pub fn run(repos_root: &Path) -> Result<String, Error> {
+    let mut state = VcsState::load(repos_root)?;
+    let stash = Stash::load(repos_root)?;
+    let rebase_state = RebaseState::load(repos_root)?;
+    let bisect_state = BisectState::load(repos_root)?;
+    let roots: Vec<VcsHash> = std::iter::once(state.head)
+        .chain(state.branches.iter().map(|x| x.commit_hash))
+        .chain(state.tags.iter().map(|x| x.commit_hash))
+        .chain(stash.entries.iter().map(|x| x.base))
+        .chain(state.reflog.hashes())
+        .chain(bisect_state.iter().flat_map(|x| x.hashes()))
+        .chain(rebase_state.iter().flat_map(|x| {
+            [x.orig_head, x.onto]
+                .into_iter()
+                .chain(x.todo.iter().chain(x.stopped.iter()).map(|x| x.hash))
+        }))
+        .collect();
+    let reachable = ancestors(&state, &roots);
+    let commits_count = state.commits.len();
+    state.commits.retain(|x| reachable.contains(&x.hash));
+    let removed = commits_count - state.commits.len();
+    state.update_vcs_dir(repos_root)?;
+    // operations pointing to removed commits cannot be undone or redone anymore
+    let mut op_log = OpLog::load(repos_root)?;
+    op_log.retain_existing(&state.commits.iter().map(|x| x.hash).collect());
+    op_log.update_vcs_dir(repos_root)?;
+
+    let kept_snapshots: HashSet<String> = (state.commits.iter().map(|x| &x.hash))
+        .chain(stash.entries.iter().map(|x| &x.hash))
+        .map(|x| format!("{}.zip", x))
+        .collect();
+    let mut removed_snapshots = 0;
+    for entry in read_dir(repos_root.join(".vcs").join("snapshots"))? {
+        let path = entry?.path();
+        // unwrap: the path is taken from "read_dir" so it has a file name
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        if !kept_snapshots.contains(file_name.as_ref()) {
+            remove_file(&path)?;
+            removed_snapshots += 1;
+        }
+    }
+    Ok(format!(
+        "Removed {} unreachable commits and {} snapshots",
+        removed, removed_snapshots
+    ))
+}
@@ -1,6 +1,6 @@
 use crate::util::branch::Branch;
 use crate::util::commit::{Commit, CommitChanges};
-use crate::util::hash::VcsHash;
+use crate::util::op_log::record_operation;
 use crate::util::snapshot::create_snapshot;
 use crate::util::vcs_state::VcsState;
 use chrono::Local;
@@ -12,26 +12,30 @@ use std::path::{Path, MAIN_SEPARATOR};
 /// Initializes VCS repository at the given path
 pub fn run(path: &Path) -> Result<String, Error> {
     if !path.try_exists()? {
-        create_dir(&path)?;
+        create_dir(path)?;
     }
-    let mut state = VcsState::init(&path)?;
-    let commit_hash = create_snapshot(&path)?;
+    let mut state = VcsState::init(path)?;
+    let time = Local::now();
+    let message = "Initial commit";
+    let commit_hash = create_snapshot(path, &Commit::snapshot_header(&[], &time, message))?;
     let commit = Commit {
         branch_name: state.branch_name.clone(),
-        time: Local::now(),
-        message: "Initial commit".to_owned(),
+        time,
+        message: message.to_owned(),
         changes: CommitChanges::default(),
         hash: commit_hash,
-        parent: VcsHash::zero(),
+        parents: vec![],
     };
     let branch = Branch {
         name: state.branch_name.clone(),
         commit_hash,
+        description: None,
     };
     state.head = commit_hash;
     state.commits.push(commit);
     state.branches.push(branch);
-    state.update_vcs_dir(&path)?;
+    state.update_refs(path, "init", "Initial commit")?;
+    record_operation(path, "init")?;
 
     Ok(format!(
         indoc! {
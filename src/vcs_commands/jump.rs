@@ -1,5 +1,9 @@
-use crate::util::commit::Commit;
-use crate::util::snapshot::{find_changes, restore_from_snapshot};
+use crate::util::graph::ancestors;
+use crate::util::merge::ensure_no_merge_in_progress;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::{ensure_no_changes, restore_from_snapshot};
 use crate::util::vcs_state::VcsState;
 use indoc::indoc;
 use std::io::{Error, ErrorKind, Result};
@@ -11,26 +15,10 @@ pub fn run(
     branch_name: &Option<String>,
     commit_hash: &Option<String>,
 ) -> Result<String> {
-    let mut state = VcsState::load(&repos_root)?;
-    let changes = find_changes(&repos_root, &state.head)?;
-    if !changes.is_empty() {
-        let mut result =
-            "error: Your local changes to the following files should be commited or dropped:\n"
-                .to_owned();
-        for rel_path in (changes.modified.iter())
-            .chain(changes.added.iter())
-            .chain(changes.deleted.iter())
-        {
-            result.extend(format!("  {}\n", rel_path).chars());
-        }
-        result.extend(
-            indoc! {
-            "Please commit your changes or drop them before you jump.
-            Aborting..."}
-            .chars(),
-        );
-        return Err(Error::new(ErrorKind::Other, result));
-    }
+    let mut state = VcsState::load(repos_root)?;
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    ensure_no_changes(repos_root, &state.head, "jump")?;
     if branch_name.is_some() && commit_hash.is_some() {
         return Err(Error::new(
             ErrorKind::InvalidInput,
@@ -56,10 +44,7 @@ pub fn run(
                 branch.commit_hash.short_str()
             );
             if name == &state.branch_name && branch.commit_hash == state.head {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("Already on branch {}", name),
-                ));
+                return Err(Error::other(format!("Already on branch {}", name)));
             }
             state.branch_name = name.clone();
             branch.commit_hash
@@ -74,53 +59,67 @@ pub fn run(
             ));
         }
     } else if let Some(hash) = commit_hash {
-        if hash.len() > 40 {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "error: provided hash prefix is longer than 40 symbols",
-            ));
+        let hash = resolve_revision(&state, hash)?;
+        // unwrap: assume the state.commits is valid
+        let commit = state.find_commit(&hash).unwrap();
+        if commit.hash == state.head {
+            return Err(Error::other(format!(
+                "Already on commit {}",
+                state.head.short_str()
+            )));
         }
-        let matching_commits: Vec<&Commit> = state
-            .commits
+        let hash = commit.hash;
+        // the branch the commit was made on may be deleted already
+        state.branch_name = if state.branches.iter().any(|x| x.name == commit.branch_name) {
+            commit.branch_name.clone()
+        } else {
+            match state
+                .branches
+                .iter()
+                .find(|x| ancestors(&state, &[x.commit_hash]).contains(&hash))
+            {
+                Some(branch) => branch.name.clone(),
+                None => {
+                    return Err(Error::other(format!(
+                        indoc! {
+                        "Commit {} does not belong to any branch.
+                        Aborting..."},
+                        hash.short_str()
+                    )))
+                }
+            }
+        };
+        let detached = state
+            .branches
             .iter()
-            .filter(|x| hash[..] == x.hash.to_string()[..hash.len()])
-            .collect();
-        if matching_commits.len() == 0 {
-            return Err(Error::new(
-                ErrorKind::NotFound,
-                format!(
-                    indoc! {
-                    "No commit with hash {} exists.
-                    Aborting..."},
-                    hash
-                ),
-            ));
-        }
-        if matching_commits.len() > 1 {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "hash is ambiguous: hash prefix matches several hashes",
-            ));
-        }
-        if matching_commits[0].hash == state.head {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("Already on commit {}", state.head.short_str()),
-            ));
-        }
-        state.branch_name = matching_commits[0].branch_name.clone();
+            .any(|x| x.name == state.branch_name && x.commit_hash != hash);
         result = format!(
-            "Successfully jumped to commit {}. Current branch: {}",
-            matching_commits[0].hash.short_str(),
-            state.branch_name
+            "Successfully jumped to commit {}. Current branch: {}{}",
+            hash.short_str(),
+            state.branch_name,
+            if detached {
+                "\nHEAD is detached, use new-branch to start a branch here"
+            } else {
+                ""
+            }
         );
-        matching_commits[0].hash
+        hash
     } else {
         // impossible panic
         panic!()
     };
+    let message = format!(
+        "Moving from {} to {}",
+        state.head.short_str(),
+        hash.short_str()
+    );
     state.head = hash;
-    restore_from_snapshot(&repos_root, &hash)?;
-    state.update_vcs_dir(&repos_root)?;
-    Ok(result)
+    restore_from_snapshot(repos_root, &hash)?;
+    state.update_refs(repos_root, "jump", &message)?;
+    let command = match branch_name {
+        Some(name) => format!("jump --branch {}", name),
+        // unwrap: either a branch or a commit is provided, it is checked above
+        None => format!("jump --commit {}", commit_hash.as_ref().unwrap()),
+    };
+    recorded(repos_root, &command, Ok(result))
 }
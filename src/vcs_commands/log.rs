@@ -1,17 +1,45 @@
+use crate::util::graph::history;
 use crate::util::hash::VcsHash;
 use crate::util::vcs_state::VcsState;
 use indoc::indoc;
+use std::collections::HashMap;
 use std::io::Error;
 use std::path::Path;
 
-/// Lists the current commit's ancestors
-pub fn run(repos_root: &Path) -> Result<String, Error> {
-    let state = VcsState::load(&repos_root)?;
-    let mut hash = state.head;
+/// Lists the current commit's ancestors, the latest go first
+///
+/// With "first_parent" only the first parent of each commit is followed
+pub fn run(repos_root: &Path, first_parent: bool) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let mut decorations = HashMap::<VcsHash, Vec<String>>::new();
+    if state.is_detached() {
+        decorations
+            .entry(state.head)
+            .or_default()
+            .push("HEAD".to_owned());
+    }
+    for branch in state.branches.iter() {
+        let name = if branch.name == state.branch_name && branch.commit_hash == state.head {
+            format!("HEAD -> {}", branch.name)
+        } else {
+            branch.name.clone()
+        };
+        decorations
+            .entry(branch.commit_hash)
+            .or_default()
+            .push(name);
+    }
+    for tag in state.tags.iter() {
+        decorations
+            .entry(tag.commit_hash)
+            .or_default()
+            .push(format!("tag: {}", tag.name));
+    }
+
     let mut result = String::new();
-    while hash != VcsHash::zero() {
+    for hash in history(&state, &[state.head], first_parent) {
         // unwrap: assume the state.commits is valid
-        let commit = state.commits.iter().find(|&x| hash == x.hash).unwrap();
+        let commit = state.find_commit(&hash).unwrap();
         let changes = if commit.changes.is_empty() {
             "No changes\n".to_owned()
         } else {
@@ -25,23 +53,51 @@ pub fn run(repos_root: &Path) -> Result<String, Error> {
             for file in commit.changes.deleted.iter() {
                 temp.extend(format!("  deleted {file}\n").chars());
             }
+            for file_move in commit.changes.renamed.iter() {
+                temp.push_str(&format!(
+                    "  renamed {} -> {} ({}%)\n",
+                    file_move.from, file_move.to, file_move.similarity
+                ));
+            }
+            for file_move in commit.changes.copied.iter() {
+                temp.push_str(&format!(
+                    "  copied {} -> {} ({}%)\n",
+                    file_move.from, file_move.to, file_move.similarity
+                ));
+            }
             temp
         };
 
         result.extend(
             format!(
-                indoc! {"commit {}
-                Date: {}
+                indoc! {"commit {}{}
+                {}Date: {}
                 Message: {}
                 {}\n"},
                 commit.hash,
+                match decorations.get(&commit.hash) {
+                    Some(x) => format!(" ({})", x.join(", ")),
+                    None => String::new(),
+                },
+                if commit.parents.len() > 1 {
+                    format!(
+                        "Merge: {}\n",
+                        commit
+                            .parents
+                            .iter()
+                            .map(|x| x.short_str())
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    )
+                } else {
+                    String::new()
+                },
                 commit.time.format("%a %b %-e %X %Y %z"),
                 commit.message,
                 changes
             )
             .chars(),
         );
-        hash = commit.parent;
     }
     result.pop();
     result.pop();
@@ -1,201 +1,552 @@
-use crate::util::archiving::unzip;
-use crate::util::commit::Commit;
+use super::cherry_pick::{pick_commits, Pick};
+use super::commit::commit_with_parents;
+use crate::util::commit::CommitChanges;
+use crate::util::diff::{ConflictStyle, Favor};
+use crate::util::graph::{ancestors, history, merge_base, merge_base_of_set};
 use crate::util::hash::VcsHash;
-use crate::util::snapshot::{find_changes, restore_from_snapshot};
+use crate::util::merge::{
+    ensure_no_merge_in_progress, merge_trees, Conflicts, MergeKind, MergeState,
+};
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::rename::DEFAULT_RENAME_THRESHOLD;
+use crate::util::snapshot::{ensure_no_changes, read_snapshot, restore_from_snapshot, write_tree};
 use crate::util::vcs_state::VcsState;
 use indoc::indoc;
-use std::collections::HashMap;
-use std::fs::{copy, create_dir, remove_dir_all, remove_file};
 use std::io::{Error, ErrorKind, Result};
 use std::path::Path;
 
-/// Merges the provided branch to master
-pub fn run(repos_root: &Path, branch: &str) -> Result<String> {
-    let mut state = VcsState::load(&repos_root)?;
+/// How the merge treats a branch that is a descendant of the current commit
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum FastForward {
+    /// Advance the current branch when possible, create a merge commit otherwise
+    #[default]
+    Allow,
+    /// Refuse the merge unless it is a fast-forward
+    Only,
+    /// Always create a merge commit
+    Never,
+}
 
-    // branches[0] is always master branch
-    let last_master_hash = state.branches[0].commit_hash;
-    if last_master_hash != state.head {
-        return Err(Error::new(
-            ErrorKind::Other,
+/// How the trees of the merged commits are combined
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Three-way merge of the changes made on both sides
+    #[default]
+    Recursive,
+    /// Record the merge keeping the current tree as it is
+    Ours,
+}
+
+/// Options of the merge command
+pub struct MergeOptions {
+    pub conflict_style: ConflictStyle,
+    /// Resolves conflicting hunks in favor of one side
+    pub favor: Favor,
+    pub strategy: Strategy,
+    pub fast_forward: FastForward,
+    /// Create an ordinary commit with the combined changes of the branch
+    pub squash: bool,
+    /// Only report what the merge would do
+    pub dry_run: bool,
+    /// Minimal similarity in percents for a file to be taken as renamed, None turns it off
+    pub rename_threshold: Option<u8>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            conflict_style: ConflictStyle::default(),
+            favor: Favor::default(),
+            strategy: Strategy::default(),
+            fast_forward: FastForward::default(),
+            squash: false,
+            dry_run: false,
+            rename_threshold: Some(DEFAULT_RENAME_THRESHOLD),
+        }
+    }
+}
+
+/// Checks that the branch can be merged into the current one and returns its last commit
+fn check_merged_branch(state: &VcsState, branch: &str) -> Result<VcsHash> {
+    // unwrap: assume the state.branch_name is valid
+    let current_branch = state
+        .branches
+        .iter()
+        .find(|x| x.name == state.branch_name)
+        .unwrap();
+    if current_branch.commit_hash != state.head {
+        return Err(Error::other(
             indoc! {
-            "The merge is possible only when you are in the last commit in master.
+            "The merge is possible only when you are in the last commit of the branch.
             Aborting..."}
             .to_owned(),
         ));
     }
-    let hash_branch = match state.branches.iter().find(|x| branch == x.name) {
-        Some(x) => x,
-        None => {
+    if branch == state.branch_name {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: cannot merge a branch into itself",
+        ));
+    }
+    match state.branches.iter().find(|x| branch == x.name) {
+        Some(x) => Ok(x.commit_hash),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: wrong branch name",
+        )),
+    }
+}
+
+impl MergeOptions {
+    fn allows_fast_forward(&self) -> bool {
+        self.fast_forward != FastForward::Never && self.strategy != Strategy::Ours && !self.squash
+    }
+}
+
+/// Merges the provided branch into the current one
+///
+/// Files changed on both sides are merged line by line, conflicts are written into the working tree
+pub fn run(repos_root: &Path, branch: &str, options: &MergeOptions) -> Result<String> {
+    let result = merge_branch(repos_root, branch, options);
+    recorded(repos_root, &format!("merge {}", branch), result)
+}
+
+fn merge_branch(repos_root: &Path, branch: &str, options: &MergeOptions) -> Result<String> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    let hash_branch = check_merged_branch(&state, branch)?;
+    // unwrap: all commits have the initial commit as an ancestor
+    let hash_ancestor = merge_base(&state, &state.head, &hash_branch).unwrap();
+    if options.dry_run {
+        return preview(
+            repos_root,
+            &state,
+            branch,
+            &hash_branch,
+            &hash_ancestor,
+            options,
+        );
+    }
+
+    ensure_no_changes(repos_root, &state.head, "merge")?;
+    if hash_ancestor == hash_branch {
+        return Ok(format!("Already up to date with branch {}", branch));
+    }
+    if hash_ancestor == state.head && options.allows_fast_forward() {
+        return fast_forward(repos_root, state, &hash_branch);
+    }
+    if options.fast_forward == FastForward::Only && hash_ancestor != state.head {
+        return Err(Error::other(format!(
+            indoc! {
+            "Branch {} has diverged from {}, fast-forward is not possible.
+            Aborting..."},
+            branch, state.branch_name
+        )));
+    }
+
+    if options.strategy == Strategy::Ours {
+        if options.squash {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
-                "error: wrong branch name",
-            ))
+                "error: the ours strategy has no changes to squash",
+            ));
         }
-    }
-    .commit_hash;
-    let hash_ancestor = {
-        let commit_by_hash = HashMap::<VcsHash, Commit>::from_iter(
-            state
-                .commits
-                .iter()
-                .map(|commit| (commit.hash, commit.clone())),
+        let message = format!(
+            "Merged branch {} into {} keeping the tree of {}.",
+            branch, state.branch_name, state.branch_name
         );
-        let mut commit = &commit_by_hash[&hash_branch];
-        while commit.branch_name != "master" {
-            commit = &commit_by_hash[&commit.parent];
-        }
-        commit.hash
+        let mut result = commit_title(&[hash_branch]).to_owned();
+        result.push_str(&commit_with_parents(
+            repos_root,
+            &message,
+            &[hash_branch],
+            "merge",
+            options.rename_threshold,
+        )?);
+        return Ok(result);
+    }
+
+    let snapshot_ancestor = read_snapshot(repos_root, &hash_ancestor)?;
+    let snapshot_current = read_snapshot(repos_root, &state.head)?;
+    let snapshot_branch = read_snapshot(repos_root, &hash_branch)?;
+    let tree_merge = merge_trees(
+        &snapshot_ancestor,
+        &snapshot_current,
+        &snapshot_branch,
+        (&state.branch_name, branch),
+        options.conflict_style,
+        options.favor,
+        options.rename_threshold,
+    );
+    write_tree(repos_root, &tree_merge.files)?;
+
+    let (message, merged) = if options.squash {
+        (squash_message(&state, branch, &hash_branch), vec![])
+    } else {
+        (
+            format!("Merged branch {} into {}.", branch, state.branch_name),
+            vec![hash_branch],
+        )
     };
+    let mut result = String::new();
+    for rel_path in tree_merge.auto_merged.iter() {
+        result.push_str(&format!("Auto-merged {}\n", rel_path));
+    }
+    if !tree_merge.conflicts.is_empty() {
+        result.push_str(&format!(
+            "Merge conflict: files have been changed both in {} and {}\n",
+            state.branch_name, branch
+        ));
+        for rel_path in tree_merge.conflicts.iter() {
+            result.push_str(&format!("  {}\n", rel_path));
+        }
+        result.push_str(indoc! {
+        "Conflicts are marked in the files. Resolve them and run merge --continue,
+        or drop the merge with merge --abort."});
+        MergeState {
+            head: state.head,
+            merged,
+            theirs: Some(hash_branch),
+            message,
+            conflicts: Conflicts::from(&tree_merge),
+            remaining: vec![],
+            kind: MergeKind::Merge,
+        }
+        .update_vcs_dir(repos_root)?;
+        return Ok(result);
+    }
 
-    {
-        let changes = find_changes(&repos_root, &state.head)?;
-        if !changes.is_empty() {
-            let mut result =
-                "Your local changes to the following files should be commited or dropped:\n"
-                    .to_owned();
-            for rel_path in (changes.modified.iter())
-                .chain(changes.added.iter())
-                .chain(changes.deleted.iter())
-            {
-                result.extend(format!("  {}\n", rel_path).chars());
-            }
-            result.extend(
-                indoc! {
-                "Please commit your changes or drop them before you jump.
-                Aborting..."}
-                .chars(),
-            );
-            return Err(Error::new(ErrorKind::Other, result));
+    result.push_str(commit_title(&merged));
+    result.push_str(&commit_with_parents(
+        repos_root,
+        &message,
+        &merged,
+        "merge",
+        options.rename_threshold,
+    )?);
+    Ok(result)
+}
+
+/// Lists the commits of the branch that are not reachable from HEAD
+fn squash_message(state: &VcsState, branch: &str, hash_branch: &VcsHash) -> String {
+    let merged = ancestors(state, &[state.head]);
+    let mut message = format!("Squashed commits of branch {}:", branch);
+    for hash in history(state, &[*hash_branch], false) {
+        if merged.contains(&hash) {
+            continue;
         }
+        // unwrap: history contains only existing commits
+        let commit = state.find_commit(&hash).unwrap();
+        message.push_str(&format!("\n  {} {}", hash.short_str(), commit.message));
     }
+    message
+}
 
-    let changes_master = find_changes(&repos_root, &hash_ancestor)?;
-    let changes_master_hashes = HashMap::<String, VcsHash>::from_iter(
-        changes_master
-            .added
-            .iter()
-            .map(|path_str| {
-                (
-                    path_str.clone(),
-                    // unwrap: file should exist
-                    VcsHash::from_file(&repos_root.join(path_str)).unwrap(),
-                )
-            })
-            .chain(changes_master.modified.iter().map(|path_str| {
-                (
-                    path_str.clone(),
-                    // unwrap: file should exist
-                    VcsHash::from_file(&repos_root.join(path_str)).unwrap(),
-                )
-            }))
-            .chain(
-                changes_master
-                    .deleted
-                    .iter()
-                    .map(|path_str| (path_str.clone(), VcsHash::zero())),
-            ),
-    );
+fn commit_title(merged: &[VcsHash]) -> &'static str {
+    if merged.is_empty() {
+        "Successfully created squash commit:\n"
+    } else {
+        "Successfully created merge commit:\n"
+    }
+}
 
-    restore_from_snapshot(&repos_root, &hash_branch)?;
-    // now the repository is branch snapshot
-    let changes_branch = find_changes(&repos_root, &hash_ancestor)?;
-    let changes_branch_hashes = HashMap::<String, VcsHash>::from_iter(
-        changes_branch
-            .added
-            .iter()
-            .map(|path_str| {
-                (
-                    path_str.clone(),
-                    // unwrap: file should exist
-                    VcsHash::from_file(&repos_root.join(path_str)).unwrap(),
-                )
-            })
-            .chain(changes_branch.modified.iter().map(|path_str| {
-                (
-                    path_str.clone(),
-                    // unwrap: file should exist
-                    VcsHash::from_file(&repos_root.join(path_str)).unwrap(),
-                )
-            }))
-            .chain(
-                changes_branch
-                    .deleted
-                    .iter()
-                    .map(|path_str| (path_str.clone(), VcsHash::zero())),
-            ),
-    );
+/// Merges several branches into the current one at once creating a commit with all of them as parents
+///
+/// Branches are merged one by one in memory. If any of them conflicts the merge is refused
+/// and the working tree is left untouched
+pub fn octopus(repos_root: &Path, branches: &[String], options: &MergeOptions) -> Result<String> {
+    let result = merge_branches(repos_root, branches, options);
+    recorded(repos_root, &format!("merge {}", branches.join(" ")), result)
+}
 
-    let mut changes_intersection = Vec::<String>::new();
-    for (path, hash1) in changes_master_hashes {
-        if let Some(&hash2) = changes_branch_hashes.get(&path) {
-            if hash1 != hash2 {
-                changes_intersection.push(path.clone());
-            }
+fn merge_branches(
+    repos_root: &Path,
+    branches: &[String],
+    options: &MergeOptions,
+) -> Result<String> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    let mut hashes = vec![];
+    for (i, branch) in branches.iter().enumerate() {
+        if branches[..i].contains(branch) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("error: branch {} is given more than once", branch),
+            ));
         }
+        hashes.push(check_merged_branch(&state, branch)?);
+    }
+    if options.fast_forward == FastForward::Only {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: several branches cannot be merged by a fast-forward",
+        ));
+    }
+    if options.strategy == Strategy::Ours && options.squash {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "error: the ours strategy has no changes to squash",
+        ));
+    }
+    if !options.dry_run {
+        ensure_no_changes(repos_root, &state.head, "merge")?;
     }
 
-    if !changes_intersection.is_empty() {
-        let mut result =
-            "Merge confilict: file has been changed both in master and branch\n".to_owned();
-        for rel_path in changes_intersection {
-            result.extend(format!("  {}\n", rel_path).chars());
+    let mut result = String::new();
+    let mut merged = vec![];
+    let mut tree = read_snapshot(repos_root, &state.head)?;
+    let mut conflicts = vec![];
+    for (branch, hash) in branches.iter().zip(hashes) {
+        let mut heads = vec![state.head];
+        heads.extend(merged.iter().map(|(_, x)| *x));
+        if ancestors(&state, &heads).contains(&hash) {
+            result.push_str(&format!("Already up to date with branch {}\n", branch));
+            continue;
+        }
+        merged.push((branch, hash));
+        if options.strategy == Strategy::Ours {
+            continue;
+        }
+        result.push_str(&format!("Trying simple merge with {}\n", branch));
+        // unwrap: all commits have the initial commit as an ancestor
+        let hash_ancestor = merge_base_of_set(&state, &heads, &hash).unwrap();
+        let tree_merge = merge_trees(
+            &read_snapshot(repos_root, &hash_ancestor)?,
+            &tree,
+            &read_snapshot(repos_root, &hash)?,
+            (&state.branch_name, branch),
+            options.conflict_style,
+            options.favor,
+            options.rename_threshold,
+        );
+        for rel_path in tree_merge.auto_merged.iter() {
+            result.push_str(&format!("Auto-merged {}\n", rel_path));
         }
-        result.extend("Aborting...".chars());
-        restore_from_snapshot(&repos_root, &state.head)?;
+        if tree_merge.conflicts.is_empty() {
+            tree = tree_merge.files;
+        } else {
+            conflicts.push((branch, tree_merge.conflicts));
+        }
+    }
+    if merged.is_empty() {
+        result.pop();
         return Ok(result);
     }
-    let snapshot_master = repos_root.join(".vcs").join("snapshot_master");
-    let archive_master = repos_root
-        .join(".vcs")
-        .join("snapshots")
-        .join(format!("{}.zip", state.head.to_string()));
-    create_dir(&snapshot_master)?;
-    unzip(&archive_master, &snapshot_master)?;
 
-    for rel_path in changes_master
-        .modified
-        .iter()
-        .chain(changes_master.added.iter())
-    {
-        let to = repos_root.join(&rel_path);
-        if !to.try_exists()? {
-            copy(snapshot_master.join(rel_path), to)?;
+    if !conflicts.is_empty() {
+        let mut report = result;
+        for (branch, paths) in conflicts {
+            report.push_str(&format!(
+                "Merge conflict: branch {} conflicts with the merged result in\n",
+                branch
+            ));
+            for rel_path in paths {
+                report.push_str(&format!("  {}\n", rel_path));
+            }
+        }
+        if options.dry_run {
+            report.push_str("The merge would be refused");
+            return Ok(report);
+        }
+        report.push_str(indoc! {
+        "Merge the branches one by one to resolve the conflicts.
+        Aborting..."});
+        return Err(Error::other(report));
+    }
+    if options.dry_run {
+        let changes = CommitChanges::between(&read_snapshot(repos_root, &state.head)?, &tree);
+        if changes.is_empty() {
+            result.push_str("No files would change");
+            return Ok(result);
+        }
+        result.push_str("Files that would change:\n");
+        for rel_path in changes.paths() {
+            result.push_str(&format!("  {}\n", rel_path));
         }
+        result.pop();
+        return Ok(result);
     }
-    remove_dir_all(snapshot_master)?;
 
-    for rel_path in changes_master.deleted {
-        let path = repos_root.join(rel_path);
-        if path.try_exists()? {
-            remove_file(path)?;
+    write_tree(repos_root, &tree)?;
+    let names: Vec<&str> = merged.iter().map(|(x, _)| x.as_str()).collect();
+    let (message, parents) = if options.squash {
+        let messages: Vec<String> = merged
+            .iter()
+            .map(|(branch, hash)| squash_message(&state, branch, hash))
+            .collect();
+        (messages.join("\n"), vec![])
+    } else {
+        (
+            format!(
+                "Merged branches {} into {}.",
+                names.join(", "),
+                state.branch_name
+            ),
+            merged.iter().map(|(_, x)| *x).collect(),
+        )
+    };
+    result.push_str(commit_title(&parents));
+    result.push_str(&commit_with_parents(
+        repos_root,
+        &message,
+        &parents,
+        "merge",
+        options.rename_threshold,
+    )?);
+    Ok(result)
+}
+
+/// Reports what the merge would do without touching the working tree or the state
+fn preview(
+    repos_root: &Path,
+    state: &VcsState,
+    branch: &str,
+    hash_branch: &VcsHash,
+    hash_ancestor: &VcsHash,
+    options: &MergeOptions,
+) -> Result<String> {
+    let mut result = format!("Merge base: {}\n", hash_ancestor.short_str());
+    if hash_ancestor == hash_branch {
+        result.push_str(&format!("Already up to date with branch {}", branch));
+        return Ok(result);
+    }
+    if *hash_ancestor == state.head && options.allows_fast_forward() {
+        result.push_str("The merge would be a fast-forward\n");
+    } else if options.fast_forward == FastForward::Only {
+        result.push_str("Fast-forward is not possible, the merge would be refused");
+        return Ok(result);
+    }
+    if options.strategy == Strategy::Ours {
+        result.push_str(&format!(
+            "The tree of {} would be kept as it is",
+            state.branch_name
+        ));
+        return Ok(result);
+    }
+
+    let snapshot_current = read_snapshot(repos_root, &state.head)?;
+    let tree_merge = merge_trees(
+        &read_snapshot(repos_root, hash_ancestor)?,
+        &snapshot_current,
+        &read_snapshot(repos_root, hash_branch)?,
+        (&state.branch_name, branch),
+        options.conflict_style,
+        options.favor,
+        options.rename_threshold,
+    );
+    let changes = CommitChanges::between(&snapshot_current, &tree_merge.files);
+    for (title, paths) in [
+        ("Files that would change:", changes.paths()),
+        (
+            "Files that would be auto-merged:",
+            tree_merge.auto_merged.iter().collect(),
+        ),
+        (
+            "Files that would conflict:",
+            tree_merge.conflicts.iter().collect(),
+        ),
+    ] {
+        if paths.is_empty() {
+            continue;
         }
+        result.push_str(title);
+        result.push('\n');
+        for rel_path in paths {
+            result.push_str(&format!("  {}\n", rel_path));
+        }
+    }
+    if changes.is_empty() {
+        result.push_str("No files would change\n");
     }
-    // unwrap: assume the state.branches is valid
-    let pos = state
+    result.pop();
+    Ok(result)
+}
+
+/// Moves the current branch and HEAD forward to the descendant commit
+fn fast_forward(repos_root: &Path, mut state: VcsState, hash: &VcsHash) -> Result<String> {
+    restore_from_snapshot(repos_root, hash)?;
+    let old_head = state.head;
+    state.head = *hash;
+    for branch in state
         .branches
-        .iter()
-        .position(|x| branch == x.name)
-        .unwrap();
-    state.branches.remove(pos);
-    for commit in state.commits.iter() {
-        if commit.branch_name == branch {
-            let snapshot_path = repos_root
-                .join(".vcs")
-                .join("snapshots")
-                .join(format!("{}.zip", commit.hash.to_string()));
-            remove_file(&snapshot_path)?;
-        }
-    }
-    state.commits = state
-        .commits
-        .into_iter()
-        .filter(|x| x.branch_name != branch)
-        .collect();
-
-    state.update_vcs_dir(&repos_root)?;
-    let mut result = "Successfully created merge commit:\n".to_owned();
-    result.extend(super::commit::run(repos_root, &format!("Merged branch {}.", branch))?.chars());
-    result.extend(format!("\nDeleted {}", branch).chars());
+        .iter_mut()
+        .filter(|x| x.name == state.branch_name)
+    {
+        branch.commit_hash = *hash;
+    }
+    state.update_refs(repos_root, "merge", "Fast-forward")?;
+    Ok(format!(
+        "Updating {}..{}\nFast-forward",
+        old_head.short_str(),
+        hash.short_str()
+    ))
+}
+
+/// Finishes the merge stopped because of conflicts once they are resolved
+///
+/// "kind" is the command continuing it, it must be the one that started the merge.
+/// The rest of the commits of a cherry-pick or revert are applied after that
+pub fn continue_merge(
+    repos_root: &Path,
+    kind: MergeKind,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    recorded(
+        repos_root,
+        &format!("{} --continue", kind.command()),
+        finish_merge(repos_root, kind, rename_threshold),
+    )
+}
+
+fn finish_merge(
+    repos_root: &Path,
+    kind: MergeKind,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let merge_state = MergeState::load_started_by(repos_root, kind)?;
+    merge_state.conflicts.ensure_resolved(repos_root)?;
+    MergeState::remove(repos_root)?;
+    let mut result = if merge_state.merged.is_empty() {
+        "Successfully created commit:\n".to_owned()
+    } else {
+        commit_title(&merge_state.merged).to_owned()
+    };
+    result.push_str(&commit_with_parents(
+        repos_root,
+        &merge_state.message,
+        &merge_state.merged,
+        kind.command(),
+        rename_threshold,
+    )?);
+    if !merge_state.remaining.is_empty() {
+        let pick = if kind == MergeKind::Revert {
+            Pick::Revert
+        } else {
+            Pick::Apply
+        };
+        result.push('\n');
+        result.push_str(&pick_commits(
+            repos_root,
+            &merge_state.remaining,
+            pick,
+            rename_threshold,
+        )?);
+    }
     Ok(result)
 }
+
+/// Drops the merge stopped because of conflicts and restores the working tree as it was before
+///
+/// "kind" is the command aborting it, it must be the one that started the merge
+pub fn abort(repos_root: &Path, kind: MergeKind) -> Result<String> {
+    let merge_state = MergeState::load_started_by(repos_root, kind)?;
+    restore_from_snapshot(repos_root, &merge_state.head)?;
+    MergeState::remove(repos_root)?;
+    Ok(format!(
+        "Merge aborted. Current commit: {}",
+        merge_state.head.short_str()
+    ))
+}
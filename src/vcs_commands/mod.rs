@@ -1,7 +1,22 @@
+pub mod bisect;
+pub mod blame;
+pub mod branch;
+pub mod checkout;
+pub mod cherry_pick;
 pub mod commit;
+pub mod gc;
 pub mod init;
 pub mod jump;
 pub mod log;
 pub mod merge;
 pub mod new_branch;
+pub mod op;
+pub mod rebase;
+pub mod reflog;
+pub mod reset;
+pub mod resolve;
+pub mod restore;
+pub mod revert;
+pub mod stash;
 pub mod status;
+pub mod tag;
@@ -1,40 +1,62 @@
 use crate::util::branch::Branch;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::{check_ref_name, resolve_revision};
+use crate::util::snapshot::{ensure_no_changes, restore_from_snapshot};
 use crate::util::vcs_state::VcsState;
 use indoc::indoc;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::path::Path;
 
-/// Creates a new branch with the given name
-pub fn run(repos_root: &Path, name: &str) -> Result<String, Error> {
-    let mut state = VcsState::load(&repos_root)?;
-    if state.branch_name != "master" {
-        return Err(Error::new(
-            ErrorKind::Other,
+/// Creates a new branch with the given name and switches to it
+///
+/// The branch starts from the "from" revision, the current commit by default.
+/// Starting from the current commit, e.g. a detached one, keeps changes in the working tree
+pub fn run(repos_root: &Path, name: &str, from: &Option<String>) -> Result<String, Error> {
+    let result = create(repos_root, name, from);
+    recorded(repos_root, &format!("new-branch {}", name), result)
+}
+
+/// Creates the branch like "run" does without recording the operation
+pub fn create(repos_root: &Path, name: &str, from: &Option<String>) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    check_ref_name(name, "branch")?;
+    if state.branches.iter().any(|x| name == x.name) {
+        return Err(Error::other(format!(
             indoc! {
-            "Creating a new branch is possible only when you are in the master branch.
-            Aborting..."}
-            .to_owned(),
-        ));
+            "Branch {} already exists.
+            Aborting..."},
+            name
+        )));
     }
-    if state.branches.iter().find(|&x| name == x.name).is_some() {
-        return Err(Error::new(
-            ErrorKind::Other,
-            format!(
-                indoc! {
-                "Branch {} already exists.
-                Aborting..."},
-                name
-            ),
-        ));
+    let hash = match from {
+        Some(revision) => resolve_revision(&state, revision)?,
+        None => state.head,
+    };
+    if hash != state.head {
+        ensure_no_changes(
+            repos_root,
+            &state.head,
+            "start a branch from another commit",
+        )?;
+        restore_from_snapshot(repos_root, &hash)?;
+        state.head = hash;
     }
     state.branches.push(Branch {
         name: name.to_owned(),
-        commit_hash: state.head,
+        commit_hash: hash,
+        description: None,
     });
     state.branch_name = name.to_owned();
-    state.update_vcs_dir(&repos_root)?;
+    state.update_refs(
+        repos_root,
+        "new-branch",
+        &format!("Created from {}", hash.short_str()),
+    )?;
     Ok(format!(
-        "Created a new branch {} from master's commit {}",
-        name, state.head.short_str()
+        "Created a new branch {} from commit {}",
+        name,
+        hash.short_str()
     ))
 }
@@ -0,0 +1,102 @@
+use crate::util::bisect::BisectState;
+use crate::util::merge::ensure_no_merge_in_progress;
+use crate::util::op_log::OpLog;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::snapshot::{ensure_no_changes, restore_from_snapshot};
+use crate::util::vcs_state::VcsState;
+use std::io::Error;
+use std::path::Path;
+
+/// Lists the recorded operations, the latest go first
+///
+/// The operation the repository is at is marked with "@"
+pub fn log(repos_root: &Path) -> Result<String, Error> {
+    let op_log = OpLog::load(repos_root)?;
+    if op_log.operations.is_empty() {
+        return Ok("No operations".to_owned());
+    }
+    Ok(op_log
+        .operations
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, operation)| {
+            format!(
+                "{} {} {} {} (HEAD {} on {})",
+                if i == op_log.current { "@" } else { " " },
+                i,
+                operation.time.format("%Y-%m-%d %X"),
+                operation.command,
+                operation.head.short_str(),
+                operation.branch_name
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Returns HEAD and branches to the positions they had before the current operation
+///
+/// The working tree is restored from the commit HEAD moves to
+pub fn undo(repos_root: &Path) -> Result<String, Error> {
+    let op_log = OpLog::load(repos_root)?;
+    if op_log.current == 0 {
+        return Err(Error::other("error: nothing to undo"));
+    }
+    let command = op_log.operations[op_log.current].command.clone();
+    let position = move_to(repos_root, op_log, false)?;
+    Ok(format!("Undid operation: {}\n{}", command, position))
+}
+
+/// Makes the undone operation again
+pub fn redo(repos_root: &Path) -> Result<String, Error> {
+    let op_log = OpLog::load(repos_root)?;
+    if op_log.current + 1 >= op_log.operations.len() {
+        return Err(Error::other("error: nothing to redo"));
+    }
+    let command = op_log.operations[op_log.current + 1].command.clone();
+    let position = move_to(repos_root, op_log, true)?;
+    Ok(format!("Redid operation: {}\n{}", command, position))
+}
+
+/// Moves the repository to the next or the previous operation of the log
+///
+/// Returns the description of the new HEAD position
+fn move_to(repos_root: &Path, mut op_log: OpLog, forward: bool) -> Result<String, Error> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    let (command, message) = if forward {
+        op_log.current += 1;
+        let operation = &op_log.operations[op_log.current];
+        ("redo", format!("Redoing {}", operation.command))
+    } else {
+        let operation = &op_log.operations[op_log.current];
+        op_log.current -= 1;
+        ("undo", format!("Undoing {}", operation.command))
+    };
+    let target = op_log.operations[op_log.current].clone();
+    if target.head != state.head {
+        ensure_no_changes(
+            repos_root,
+            &state.head,
+            &format!("{} an operation", command),
+        )?;
+        restore_from_snapshot(repos_root, &target.head)?;
+    }
+    state.head = target.head;
+    state.branch_name = target.branch_name;
+    state.branches = target.branches;
+    state.update_refs(repos_root, command, &message)?;
+    match target.bisect {
+        Some(bisect) => bisect.update_vcs_dir(repos_root)?,
+        None if BisectState::load(repos_root)?.is_some() => BisectState::remove(repos_root)?,
+        None => {}
+    }
+    op_log.update_vcs_dir(repos_root)?;
+    Ok(format!(
+        "HEAD is at {} on branch {}",
+        state.head.short_str(),
+        state.branch_name
+    ))
+}
@@ -0,0 +1,431 @@
+use super::commit::{amend_head, commit_with_parents};
+use crate::util::commit::Commit;
+use crate::util::diff::{ConflictStyle, Favor};
+use crate::util::graph::{ancestors, history};
+use crate::util::hash::VcsHash;
+use crate::util::merge::{ensure_no_merge_in_progress, merge_trees, Conflicts};
+use crate::util::op_log::recorded;
+use crate::util::rebase::{
+    ensure_no_rebase_in_progress, format_todo, parse_todo, RebaseState, TodoAction, TodoStep,
+};
+use crate::util::revision::{find_commit_by_prefix, resolve_revision};
+use crate::util::snapshot::{
+    ensure_no_changes, find_changes, read_snapshot, restore_from_snapshot, write_tree,
+};
+use crate::util::vcs_state::VcsState;
+use indoc::indoc;
+use std::fs::{read_to_string, write};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Replays the commits of the current branch that are not in "upstream" on top of it one by one
+///
+/// Merge commits are not replayed, they are listed as skipped. A step with conflicts stops the rebase until
+/// it is continued, skipped or aborted
+pub fn run(repos_root: &Path, upstream: &str, rename_threshold: Option<u8>) -> Result<String> {
+    let result = start(repos_root, upstream, rename_threshold, Ok);
+    recorded(repos_root, &format!("rebase {}", upstream), result)
+}
+
+/// Rebases the current branch following the todo list edited by the user
+///
+/// The list is read from "todo_file" if it is provided, otherwise it is opened in $EDITOR
+pub fn interactive(
+    repos_root: &Path,
+    upstream: &str,
+    todo_file: &Option<PathBuf>,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let result = start(repos_root, upstream, rename_threshold, |steps| {
+        let state = VcsState::load(repos_root)?;
+        let text = match todo_file {
+            Some(path) => read_to_string(path)?,
+            None => {
+                let commits: Vec<_> = steps
+                    .iter()
+                    .map(|x| {
+                        // unwrap: the steps are built from existing commits
+                        let commit = state.find_commit(&x.hash).unwrap();
+                        (x.hash, commit.message.clone())
+                    })
+                    .collect();
+                edit_todo(repos_root, &format_todo(&commits))?
+            }
+        };
+        parse_todo(&text, |prefix| {
+            let hash = find_commit_by_prefix(&state, prefix)?.hash;
+            if steps.iter().any(|x| x.hash == hash) {
+                Ok(hash)
+            } else {
+                Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("error: commit {} is not rebased", prefix),
+                ))
+            }
+        })
+    });
+    recorded(repos_root, &format!("rebase -i {}", upstream), result)
+}
+
+/// Opens the todo list in the editor and returns the edited text
+fn edit_todo(repos_root: &Path, text: &str) -> Result<String> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|x| std::env::var(x).ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                "error: set EDITOR or provide the todo list with --todo <FILE>",
+            )
+        })?;
+    let path = repos_root.join(".vcs").join("rebase_todo");
+    write(&path, text)?;
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "error: EDITOR is empty"))?;
+    let status = Command::new(program).args(words).arg(&path).status()?;
+    if !status.success() {
+        return Err(Error::other(format!(
+            indoc! {
+            "The editor exited with {}.
+            Aborting..."},
+            status
+        )));
+    }
+    read_to_string(&path)
+}
+
+/// Starts the rebase onto "upstream" with the todo list made by "make_todo" from the default one
+fn start(
+    repos_root: &Path,
+    upstream: &str,
+    rename_threshold: Option<u8>,
+    make_todo: impl FnOnce(Vec<TodoStep>) -> Result<Vec<TodoStep>>,
+) -> Result<String> {
+    ensure_no_merge_in_progress(repos_root)?;
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    if !state
+        .branches
+        .iter()
+        .any(|x| x.name == state.branch_name && x.commit_hash == state.head)
+    {
+        return Err(Error::other(indoc! {
+        "The rebase is possible only when you are in the last commit of the branch.
+        Aborting..."}));
+    }
+    let onto = resolve_revision(&state, upstream)?;
+    ensure_no_changes(repos_root, &state.head, "rebase")?;
+
+    let upstream_commits = ancestors(&state, &[onto]);
+    let (merges, commits): (Vec<VcsHash>, Vec<VcsHash>) = history(&state, &[state.head], false)
+        .into_iter()
+        .rev()
+        .filter(|x| !upstream_commits.contains(x))
+        // unwrap: history contains only existing commits
+        .partition(|x| state.find_commit(x).unwrap().parents.len() > 1);
+    let mut skipped = String::new();
+    for hash in merges.iter() {
+        skipped.push_str(&format!(
+            "Skipped merge commit {} {}\n",
+            hash.short_str(),
+            // unwrap: history contains only existing commits
+            state.find_commit(hash).unwrap().message
+        ));
+    }
+    let steps: Vec<TodoStep> = commits
+        .into_iter()
+        .map(|hash| TodoStep {
+            action: TodoAction::Pick,
+            hash,
+            message: None,
+        })
+        .collect();
+    let default_todo = steps.clone();
+    let todo = make_todo(steps)?;
+    if todo.is_empty() && !default_todo.is_empty() {
+        return Ok(skipped + "Nothing to do, the todo list is empty");
+    }
+    if todo == default_todo && ancestors(&state, &[state.head]).contains(&onto) {
+        return Ok(format!(
+            "{}Current branch {} is up to date with {}",
+            skipped, state.branch_name, upstream
+        ));
+    }
+
+    let rebase_state = RebaseState {
+        branch_name: state.branch_name.clone(),
+        orig_head: state.head,
+        onto,
+        todo,
+        stopped: None,
+        conflicts: Conflicts::default(),
+        editing: false,
+    };
+    for branch in state
+        .branches
+        .iter_mut()
+        .filter(|x| x.name == rebase_state.branch_name)
+    {
+        branch.commit_hash = onto;
+    }
+    state.head = onto;
+    state.update_refs(
+        repos_root,
+        "rebase",
+        &format!("Moving onto {}", onto.short_str()),
+    )?;
+    restore_from_snapshot(repos_root, &onto)?;
+    rebase_state.update_vcs_dir(repos_root)?;
+    Ok(skipped + &replay(repos_root, rebase_state, rename_threshold)?)
+}
+
+/// Finishes the stopped step and replays the rest of the commits
+///
+/// Conflicts must be resolved. Changes made while editing a commit are amended to it
+pub fn continue_rebase(repos_root: &Path, rename_threshold: Option<u8>) -> Result<String> {
+    recorded(
+        repos_root,
+        "rebase --continue",
+        continue_steps(repos_root, rename_threshold),
+    )
+}
+
+fn continue_steps(repos_root: &Path, rename_threshold: Option<u8>) -> Result<String> {
+    let mut rebase_state = load_in_progress(repos_root)?;
+    let mut result = String::new();
+    if rebase_state.editing {
+        let state = VcsState::load(repos_root)?;
+        if !find_changes(repos_root, &state.head)?.is_empty() {
+            // unwrap: assume the state.commits is valid
+            let message = state.find_commit(&state.head).unwrap().message.clone();
+            amend_head(repos_root, &message, "rebase", rename_threshold)?;
+            result.push_str(&format!(
+                "Amended {} {}\n",
+                VcsState::load(repos_root)?.head.short_str(),
+                message
+            ));
+        }
+        rebase_state.editing = false;
+        rebase_state.stopped = None;
+    } else if let Some(step) = rebase_state.stopped.take() {
+        rebase_state.conflicts.ensure_resolved(repos_root)?;
+        rebase_state.conflicts = Conflicts::default();
+        let state = VcsState::load(repos_root)?;
+        // unwrap: assume the rebase state is valid
+        let commit = state.find_commit(&step.hash).unwrap().clone();
+        result.push_str(&commit_step(
+            repos_root,
+            &rebase_state,
+            &step,
+            &commit,
+            rename_threshold,
+        )?);
+        if step.action == TodoAction::Edit {
+            return stop_to_edit(repos_root, rebase_state, step, result);
+        }
+    }
+    rebase_state.update_vcs_dir(repos_root)?;
+    result.push_str(&replay(repos_root, rebase_state, rename_threshold)?);
+    Ok(result)
+}
+
+/// Drops the stopped step and replays the rest of the commits
+pub fn skip(repos_root: &Path, rename_threshold: Option<u8>) -> Result<String> {
+    recorded(
+        repos_root,
+        "rebase --skip",
+        skip_step(repos_root, rename_threshold),
+    )
+}
+
+fn skip_step(repos_root: &Path, rename_threshold: Option<u8>) -> Result<String> {
+    let mut rebase_state = load_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    restore_from_snapshot(repos_root, &state.head)?;
+    let mut result = String::new();
+    if let Some(step) = rebase_state.stopped.take() {
+        if !rebase_state.editing {
+            // unwrap: assume the rebase state is valid
+            let commit = state.find_commit(&step.hash).unwrap();
+            result.push_str(&format!(
+                "Skipped {} {}\n",
+                step.hash.short_str(),
+                commit.message
+            ));
+        }
+    }
+    rebase_state.editing = false;
+    rebase_state.conflicts = Conflicts::default();
+    rebase_state.update_vcs_dir(repos_root)?;
+    result.push_str(&replay(repos_root, rebase_state, rename_threshold)?);
+    Ok(result)
+}
+
+/// Stops the rebase and returns the branch to the commit it was at before
+pub fn abort(repos_root: &Path) -> Result<String> {
+    recorded(repos_root, "rebase --abort", abort_rebase(repos_root))
+}
+
+fn abort_rebase(repos_root: &Path) -> Result<String> {
+    let rebase_state = load_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    for branch in state
+        .branches
+        .iter_mut()
+        .filter(|x| x.name == rebase_state.branch_name)
+    {
+        branch.commit_hash = rebase_state.orig_head;
+    }
+    state.branch_name = rebase_state.branch_name.clone();
+    state.head = rebase_state.orig_head;
+    state.update_refs(
+        repos_root,
+        "rebase",
+        &format!(
+            "Aborted, returning to {}",
+            rebase_state.orig_head.short_str()
+        ),
+    )?;
+    restore_from_snapshot(repos_root, &rebase_state.orig_head)?;
+    RebaseState::remove(repos_root)?;
+    Ok(format!(
+        "Rebase aborted. Current commit: {}",
+        rebase_state.orig_head.short_str()
+    ))
+}
+
+fn load_in_progress(repos_root: &Path) -> Result<RebaseState> {
+    match RebaseState::load(repos_root)? {
+        Some(x) => Ok(x),
+        None => Err(Error::other("error: there is no rebase in progress")),
+    }
+}
+
+/// Does the steps left in the todo list until a conflict, an edit or the end
+fn replay(
+    repos_root: &Path,
+    mut rebase_state: RebaseState,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let mut result = String::new();
+    while !rebase_state.todo.is_empty() {
+        let step = rebase_state.todo.remove(0);
+        let state = VcsState::load(repos_root)?;
+        // unwrap: assume the rebase state is valid
+        let commit = state.find_commit(&step.hash).unwrap().clone();
+        let tree_merge = merge_trees(
+            &read_snapshot(repos_root, &commit.first_parent())?,
+            &read_snapshot(repos_root, &state.head)?,
+            &read_snapshot(repos_root, &step.hash)?,
+            (
+                "HEAD",
+                &format!("{} ({})", step.hash.short_str(), commit.message),
+            ),
+            ConflictStyle::Merge,
+            Favor::None,
+            rename_threshold,
+        );
+        write_tree(repos_root, &tree_merge.files)?;
+        if !tree_merge.conflicts.is_empty() {
+            result.push_str(&format!(
+                "Could not apply {} {}\n",
+                step.hash.short_str(),
+                commit.message
+            ));
+            for rel_path in tree_merge.conflicts.iter() {
+                result.push_str(&format!("  {}\n", rel_path));
+            }
+            result.push_str(indoc! {
+            "Conflicts are marked in the files. Resolve them and run rebase --continue,
+            skip the commit with rebase --skip or stop with rebase --abort."});
+            rebase_state.stopped = Some(step);
+            rebase_state.conflicts = Conflicts::from(&tree_merge);
+            rebase_state.update_vcs_dir(repos_root)?;
+            return Ok(result);
+        }
+        result.push_str(&commit_step(
+            repos_root,
+            &rebase_state,
+            &step,
+            &commit,
+            rename_threshold,
+        )?);
+        if step.action == TodoAction::Edit {
+            return stop_to_edit(repos_root, rebase_state, step, result);
+        }
+        rebase_state.update_vcs_dir(repos_root)?;
+    }
+    RebaseState::remove(repos_root)?;
+    result.push_str(&format!(
+        "Successfully rebased branch {} onto {}",
+        rebase_state.branch_name,
+        rebase_state.onto.short_str()
+    ));
+    Ok(result)
+}
+
+fn stop_to_edit(
+    repos_root: &Path,
+    mut rebase_state: RebaseState,
+    step: TodoStep,
+    mut result: String,
+) -> Result<String> {
+    rebase_state.stopped = Some(step);
+    rebase_state.editing = true;
+    rebase_state.update_vcs_dir(repos_root)?;
+    result.push_str(&format!(
+        indoc! {
+        "Stopped at {}. Change the working tree and run rebase --continue
+        to amend the commit with the changes."},
+        VcsState::load(repos_root)?.head.short_str()
+    ));
+    Ok(result)
+}
+
+/// Commits the working tree as the replayed "commit" according to the step
+///
+/// Squash and fixup amend the previous commit, other steps create a new one if there are changes
+fn commit_step(
+    repos_root: &Path,
+    rebase_state: &RebaseState,
+    step: &TodoStep,
+    commit: &Commit,
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    let state = VcsState::load(repos_root)?;
+    let meld = matches!(step.action, TodoAction::Squash | TodoAction::Fixup)
+        && state.head != rebase_state.onto;
+    if meld {
+        // unwrap: assume the state.commits is valid
+        let previous = state.find_commit(&state.head).unwrap();
+        let message = if step.action == TodoAction::Squash {
+            format!("{}\n\n{}", previous.message, commit.message)
+        } else {
+            previous.message.clone()
+        };
+        amend_head(repos_root, &message, "rebase", rename_threshold)?;
+        return Ok(format!(
+            "Melded {} into {}\n",
+            commit.hash.short_str(),
+            VcsState::load(repos_root)?.head.short_str()
+        ));
+    }
+    if find_changes(repos_root, &state.head)?.is_empty() {
+        return Ok(format!(
+            "Dropped {} {}: its changes are already applied\n",
+            commit.hash.short_str(),
+            commit.message
+        ));
+    }
+    let message = step.message.as_ref().unwrap_or(&commit.message);
+    commit_with_parents(repos_root, message, &[], "rebase", rename_threshold)?;
+    Ok(format!(
+        "Applied {} -> {} {}\n",
+        commit.hash.short_str(),
+        VcsState::load(repos_root)?.head.short_str(),
+        message
+    ))
+}
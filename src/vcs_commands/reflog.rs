@@ -0,0 +1,56 @@
+use crate::util::vcs_state::VcsState;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Lists movements of the ref, HEAD by default, the latest go first
+///
+/// Every entry can be referred to as "<ref>@{n}"
+pub fn run(repos_root: &Path, name: &Option<String>) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let name = name.as_deref().unwrap_or("HEAD");
+    let Some(entries) = state.reflog.refs.get(name) else {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("error: no reflog for {}", name),
+        ));
+    };
+    if entries.is_empty() {
+        return Ok(format!("Reflog of {} is empty", name));
+    }
+    Ok(entries
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(n, entry)| {
+            // unwrap: an entry has the old or the new commit
+            let hash = entry.new.or(entry.old).unwrap();
+            format!(
+                "{} {}@{{{}}}: {}: {}",
+                hash.short_str(),
+                name,
+                n,
+                entry.command,
+                entry.message
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Drops the entries of the ref or of all refs if it is not provided
+///
+/// Commits kept only by the dropped entries are removed by the next gc
+pub fn expire(repos_root: &Path, name: &Option<String>) -> Result<String, Error> {
+    let mut state = VcsState::load(repos_root)?;
+    if let Some(name) = name {
+        if !state.reflog.refs.contains_key(name) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("error: no reflog for {}", name),
+            ));
+        }
+    }
+    let removed = state.reflog.expire(name.as_deref());
+    state.update_vcs_dir(repos_root)?;
+    Ok(format!("Expired {} reflog entries", removed))
+}
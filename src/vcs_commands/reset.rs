@@ -0,0 +1,80 @@
+use crate::util::merge::MergeState;
+use crate::util::op_log::recorded;
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::restore_from_snapshot;
+use crate::util::vcs_state::VcsState;
+use indoc::indoc;
+use std::io::{Error, Result};
+use std::path::Path;
+
+/// What happens to the working tree when the branch is reset
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResetMode {
+    /// Keep the working tree, the changes between the commits become local changes
+    Soft,
+    /// The same as "Soft" since there is no staging area
+    #[default]
+    Mixed,
+    /// Make the working tree match the new commit dropping all local changes
+    Hard,
+}
+
+impl ResetMode {
+    fn flag(&self) -> &'static str {
+        match self {
+            ResetMode::Soft => "--soft",
+            ResetMode::Mixed => "--mixed",
+            ResetMode::Hard => "--hard",
+        }
+    }
+}
+
+/// Moves the current branch and HEAD to the revision, only HEAD if it is detached
+///
+/// Commits that are left behind are kept until gc. A hard reset also drops a merge in progress
+pub fn run(repos_root: &Path, revision: &str, mode: ResetMode) -> Result<String> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let merge_state = MergeState::load(repos_root)?;
+    if merge_state.is_some() && mode != ResetMode::Hard {
+        return Err(Error::other(indoc! {
+        "error: a merge is in progress.
+        Use reset --hard or merge --abort to drop it.
+        Aborting..."}));
+    }
+    let mut state = VcsState::load(repos_root)?;
+    let hash = resolve_revision(&state, revision)?;
+    if mode == ResetMode::Hard {
+        restore_from_snapshot(repos_root, &hash)?;
+        if merge_state.is_some() {
+            MergeState::remove(repos_root)?;
+        }
+    }
+    let detached = state.is_detached();
+    if !detached {
+        for branch in state
+            .branches
+            .iter_mut()
+            .filter(|x| x.name == state.branch_name)
+        {
+            branch.commit_hash = hash;
+        }
+    }
+    state.head = hash;
+    state.update_refs(repos_root, "reset", &format!("Moving to {}", revision))?;
+    // unwrap: the revision is resolved to an existing commit
+    let commit = state.find_commit(&hash).unwrap();
+    let target = if detached {
+        "HEAD".to_owned()
+    } else {
+        format!("Branch {}", state.branch_name)
+    };
+    let result = format!(
+        "{} is now at {} {}",
+        target,
+        hash.short_str(),
+        commit.message
+    );
+    let command = format!("reset {} {}", mode.flag(), revision);
+    recorded(repos_root, &command, Ok(result))
+}
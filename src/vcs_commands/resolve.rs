@@ -0,0 +1,35 @@
+use crate::util::merge::MergeState;
+use crate::util::rebase::RebaseState;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Marks conflicting files of the stopped merge or rebase step as resolved
+///
+/// It is needed for files without conflict markers: binary ones and ones deleted on one side.
+/// The working tree version of the files is taken as the resolution
+pub fn run(repos_root: &Path, paths: &[String]) -> Result<String, Error> {
+    let selected = if let Some(mut merge_state) = MergeState::load(repos_root)? {
+        let selected = merge_state.conflicts.mark_resolved(paths);
+        merge_state.update_vcs_dir(repos_root)?;
+        selected
+    } else if let Some(mut rebase_state) = RebaseState::load(repos_root)? {
+        let selected = rebase_state.conflicts.mark_resolved(paths);
+        rebase_state.update_vcs_dir(repos_root)?;
+        selected
+    } else {
+        return Err(Error::other(
+            "error: there is no merge or rebase stopped because of conflicts",
+        ));
+    };
+    if selected.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "error: the paths did not match any conflicting file",
+        ));
+    }
+    Ok(selected
+        .iter()
+        .map(|x| format!("Resolved {}", x))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
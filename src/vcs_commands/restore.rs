@@ -0,0 +1,88 @@
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::resolve_revision;
+use crate::util::snapshot::{path_matches, read_snapshot, working_tree_files};
+use crate::util::vcs_state::VcsState;
+use std::fs::{create_dir_all, read, remove_file, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Restores the given paths of the working tree from a commit without moving HEAD
+///
+/// Files that are absent in the source commit are deleted only if "delete_added" is set
+pub fn run(
+    repos_root: &Path,
+    paths: &[String],
+    source: &Option<String>,
+    delete_added: bool,
+) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    let source_hash = resolve_revision(&state, source.as_deref().unwrap_or("HEAD"))?;
+    let snapshot = read_snapshot(repos_root, &source_hash)?;
+    let working_files = working_tree_files(repos_root)?;
+
+    for path in paths {
+        if !snapshot
+            .keys()
+            .chain(working_files.iter())
+            .any(|x| path_matches(x, std::slice::from_ref(path)))
+        {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("error: path {} did not match any file", path),
+            ));
+        }
+    }
+
+    let mut restored = vec![];
+    for (rel_path, contents) in snapshot.iter() {
+        if !path_matches(rel_path, paths) {
+            continue;
+        }
+        let path = repos_root.join(rel_path);
+        if path.is_file() && read(&path)? == *contents {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(&path, contents)?;
+        restored.push(rel_path);
+    }
+    let mut added = vec![];
+    for rel_path in working_files.iter() {
+        if path_matches(rel_path, paths) && !snapshot.contains_key(rel_path) {
+            if delete_added {
+                remove_file(repos_root.join(rel_path))?;
+            }
+            added.push(rel_path);
+        }
+    }
+
+    if restored.is_empty() && (added.is_empty() || !delete_added) {
+        let mut result = "Nothing to restore".to_owned();
+        if !added.is_empty() {
+            result.push_str("\nAdded files are kept (use --delete-added to remove them):");
+            for rel_path in added {
+                result.push_str(&format!("\n  {}", rel_path));
+            }
+        }
+        return Ok(result);
+    }
+    let mut result = format!("Restored files from commit {}\n", source_hash.short_str());
+    for rel_path in restored {
+        result.push_str(&format!("  restored {}\n", rel_path));
+    }
+    if delete_added {
+        for rel_path in added {
+            result.push_str(&format!("  deleted {}\n", rel_path));
+        }
+    } else if !added.is_empty() {
+        result.push_str("Added files are kept (use --delete-added to remove them):\n");
+        for rel_path in added {
+            result.push_str(&format!("  {}\n", rel_path));
+        }
+    }
+    result.pop();
+    Ok(result)
+}
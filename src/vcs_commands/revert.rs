@@ -0,0 +1,12 @@
+use super::cherry_pick::{apply, Pick};
+use std::io::Result;
+use std::path::Path;
+
+/// Creates new commits undoing the changes the commits introduced relative to their parents
+pub fn run(
+    repos_root: &Path,
+    revisions: &[String],
+    rename_threshold: Option<u8>,
+) -> Result<String> {
+    apply(repos_root, revisions, Pick::Revert, rename_threshold)
+}
@@ -0,0 +1,201 @@
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::snapshot::{
+    create_snapshot, find_changes_with_moves, read_snapshot, restore_from_snapshot,
+};
+use crate::util::stash::{Stash, StashEntry};
+use crate::util::vcs_state::VcsState;
+use chrono::Local;
+use std::fs::{create_dir_all, read, remove_file, write};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+fn get_entry(stash: &Stash, index: usize) -> Result<&StashEntry, Error> {
+    stash.entries.get(index).ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("error: stash@{{{}}} does not exist", index),
+        )
+    })
+}
+
+/// Saves local changes to the stash and restores the current commit in the working tree
+pub fn push(
+    repos_root: &Path,
+    message: &Option<String>,
+    rename_threshold: Option<u8>,
+) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let state = VcsState::load(repos_root)?;
+    let changes = find_changes_with_moves(repos_root, &state.head, rename_threshold)?;
+    if changes.is_empty() {
+        return Err(Error::other("No local changes to save"));
+    }
+    let message = match message {
+        Some(x) => format!("On {}: {}", state.branch_name, x),
+        None => {
+            // unwrap: assume the state.commits is valid
+            let commit = state.find_commit(&state.head).unwrap();
+            format!(
+                "WIP on {}: {} {}",
+                state.branch_name,
+                state.head.short_str(),
+                commit.message
+            )
+        }
+    };
+    let time = Local::now();
+    let hash = create_snapshot(
+        repos_root,
+        &format!("stash {}\n{}\n{}", state.head, time.to_rfc3339(), message),
+    )?;
+    let mut stash = Stash::load(repos_root)?;
+    stash.entries.insert(
+        0,
+        StashEntry {
+            hash,
+            base: state.head,
+            branch_name: state.branch_name.clone(),
+            time,
+            message: message.clone(),
+            changes,
+        },
+    );
+    stash.update_vcs_dir(repos_root)?;
+    restore_from_snapshot(repos_root, &state.head)?;
+    Ok(format!(
+        "Saved working directory as stash@{{0}}: {}",
+        message
+    ))
+}
+
+/// Lists the stash entries, the newest goes first
+pub fn list(repos_root: &Path) -> Result<String, Error> {
+    let stash = Stash::load(repos_root)?;
+    if stash.entries.is_empty() {
+        return Ok("No stash entries".to_owned());
+    }
+    Ok(stash
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| format!("stash@{{{}}}: {}", i, entry.message))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Shows the changes recorded in the stash entry
+pub fn show(repos_root: &Path, index: usize) -> Result<String, Error> {
+    let stash = Stash::load(repos_root)?;
+    let entry = get_entry(&stash, index)?;
+    let mut result = format!(
+        "stash@{{{}}}: {}\nDate: {}\nBase: {}\n",
+        index,
+        entry.message,
+        entry.time.format("%a %b %-e %X %Y %z"),
+        entry.base.short_str()
+    );
+    for file in entry.changes.modified.iter() {
+        result.push_str(&format!("  modified: {file}\n"));
+    }
+    for file in entry.changes.added.iter() {
+        result.push_str(&format!("  new file: {file}\n"));
+    }
+    for file in entry.changes.deleted.iter() {
+        result.push_str(&format!("  deleted:  {file}\n"));
+    }
+    for file_move in entry.changes.renamed.iter() {
+        result.push_str(&format!(
+            "  renamed:  {} -> {}\n",
+            file_move.from, file_move.to
+        ));
+    }
+    for file_move in entry.changes.copied.iter() {
+        result.push_str(&format!(
+            "  copied:   {} -> {}\n",
+            file_move.from, file_move.to
+        ));
+    }
+    result.pop();
+    Ok(result)
+}
+
+/// Applies the stashed changes to the working tree, the current commit may differ from the base
+///
+/// A file is in conflict if it differs from both its base and its stashed version.
+/// Conflicting files are left untouched. With "pop" the entry is dropped if there are no conflicts
+pub fn apply(repos_root: &Path, index: usize, pop: bool) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut stash = Stash::load(repos_root)?;
+    let entry = get_entry(&stash, index)?.clone();
+    let base = read_snapshot(repos_root, &entry.base)?;
+    let stashed = read_snapshot(repos_root, &entry.hash)?;
+
+    let mut conflicts = vec![];
+    for rel_path in entry.changes.paths() {
+        let path = repos_root.join(rel_path);
+        let current = if path.is_file() {
+            Some(read(&path)?)
+        } else {
+            None
+        };
+        let stashed_contents = stashed.get(rel_path);
+        if current.as_ref() == stashed_contents {
+            continue;
+        }
+        if current.as_ref() != base.get(rel_path) {
+            conflicts.push(rel_path);
+            continue;
+        }
+        match stashed_contents {
+            Some(contents) => {
+                if let Some(parent) = path.parent() {
+                    create_dir_all(parent)?;
+                }
+                write(&path, contents)?;
+            }
+            None => remove_file(&path)?,
+        }
+    }
+
+    if !conflicts.is_empty() {
+        let mut result = format!(
+            "Conflict: the following files have been changed since stash@{{{}}} was created\n",
+            index
+        );
+        for rel_path in conflicts {
+            result.push_str(&format!("  {}\n", rel_path));
+        }
+        result.push_str("They are left untouched, other changes are applied");
+        if pop {
+            result.push_str("\nThe stash entry is kept in case you need it again");
+        }
+        return Ok(result);
+    }
+    let mut result = format!("Applied stash@{{{}}}: {}", index, entry.message);
+    if pop {
+        stash.entries.remove(index);
+        stash.update_vcs_dir(repos_root)?;
+        remove_snapshot(repos_root, &entry)?;
+        result.push_str(&format!("\nDropped stash@{{{}}}", index));
+    }
+    Ok(result)
+}
+
+/// Removes the stash entry together with its snapshot
+pub fn drop(repos_root: &Path, index: usize) -> Result<String, Error> {
+    let mut stash = Stash::load(repos_root)?;
+    let entry = get_entry(&stash, index)?.clone();
+    stash.entries.remove(index);
+    stash.update_vcs_dir(repos_root)?;
+    remove_snapshot(repos_root, &entry)?;
+    Ok(format!("Dropped stash@{{{}}}: {}", index, entry.message))
+}
+
+fn remove_snapshot(repos_root: &Path, entry: &StashEntry) -> Result<(), Error> {
+    remove_file(
+        repos_root
+            .join(".vcs")
+            .join("snapshots")
+            .join(format!("{}.zip", entry.hash)),
+    )
+}
@@ -1,17 +1,90 @@
-use crate::util::snapshot::find_changes;
+use crate::util::bisect::BisectState;
+use crate::util::merge::MergeState;
+use crate::util::rebase::RebaseState;
+use crate::util::snapshot::find_changes_with_moves;
 use crate::util::vcs_state::VcsState;
 use std::io::Error;
 use std::path::Path;
 
-/// Shows the working tree status
-pub fn run(repos_root: &Path) -> Result<String, Error> {
-    let state = VcsState::load(&repos_root)?;
-    let changes = find_changes(&repos_root, &state.head)?;
-    if changes.is_empty() {
+/// Shows the working tree status, files are taken as renamed or copied with "rename_threshold"
+pub fn run(repos_root: &Path, rename_threshold: Option<u8>) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let changes = find_changes_with_moves(repos_root, &state.head, rename_threshold)?;
+    let merge_state = MergeState::load(repos_root)?;
+    let rebase_state = RebaseState::load(repos_root)?;
+    let bisect_state = BisectState::load(repos_root)?;
+    let detached = state.is_detached() && rebase_state.is_none();
+    if changes.is_empty()
+        && merge_state.is_none()
+        && rebase_state.is_none()
+        && bisect_state.is_none()
+        && !detached
+    {
         return Ok("No changes to be committed".to_owned());
     }
-    let mut result = format!("On branch {}\n", state.branch_name);
-    result.extend("Changes to be committed:\n".chars());
+    let mut result = if detached {
+        let mut header = format!("HEAD detached at {}\n", state.head.short_str());
+        header.push_str("  (use \"new-branch\" or \"commit --branch\" to start a branch here)\n");
+        if changes.is_empty() && merge_state.is_none() {
+            header.push_str("No changes to be committed\n");
+        }
+        header
+    } else {
+        format!("On branch {}\n", state.branch_name)
+    };
+    if let Some(bisect_state) = bisect_state {
+        result.push_str(&format!(
+            "You are currently bisecting, started from branch {}.\n",
+            bisect_state.orig_branch
+        ));
+        result.push_str("  (use \"bisect reset\" to get back to the original branch)\n");
+    }
+    if let Some(rebase_state) = rebase_state {
+        result.push_str(&format!(
+            "You are currently rebasing branch {} onto {}, {} commits left.\n",
+            rebase_state.branch_name,
+            rebase_state.onto.short_str(),
+            rebase_state.todo.len()
+        ));
+        if rebase_state.editing {
+            result.push_str(&format!("Stopped to edit {}.\n", state.head.short_str()));
+            result.push_str("  (change the working tree and run \"rebase --continue\")\n");
+        } else if let Some(step) = rebase_state.stopped {
+            result.push_str(&format!("Stopped at {}.\n", step.hash.short_str()));
+            result.push_str("  (fix conflicts and run \"rebase --continue\")\n");
+            result.push_str("  (use \"rebase --skip\" to skip this commit)\n");
+            result.push_str("  (use \"rebase --abort\" to return to the original branch)\n");
+        }
+    }
+    if let Some(merge_state) = merge_state {
+        let unresolved = merge_state.conflicts.unresolved(repos_root)?;
+        let command = merge_state.kind.command();
+        if unresolved.is_empty() {
+            result.push_str("All conflicts fixed but you are still merging.\n");
+            result.push_str(&format!(
+                "  (use \"{} --continue\" to conclude merge)\n",
+                command
+            ));
+        } else {
+            result.push_str("You have unmerged paths.\n");
+            result.push_str(&format!(
+                "  (fix conflicts and run \"{} --continue\")\n",
+                command
+            ));
+            result.push_str("  (use \"resolve <path>...\" to mark resolution)\n");
+            result.push_str(&format!(
+                "  (use \"{} --abort\" to abort the merge)\n",
+                command
+            ));
+            result.push_str("Unmerged paths:\n");
+            for file in unresolved {
+                result.push_str(&format!("  both modified: {file}\n"));
+            }
+        }
+    }
+    if !changes.is_empty() {
+        result.push_str("Changes to be committed:\n");
+    }
 
     for file in changes.modified {
         result.extend(format!("  modified: {file}\n").chars());
@@ -22,6 +95,18 @@ pub fn run(repos_root: &Path) -> Result<String, Error> {
     for file in changes.deleted {
         result.extend(format!("  deleted:  {file}\n").chars());
     }
+    for file_move in changes.renamed {
+        result.push_str(&format!(
+            "  renamed:  {} -> {}\n",
+            file_move.from, file_move.to
+        ));
+    }
+    for file_move in changes.copied {
+        result.push_str(&format!(
+            "  copied:   {} -> {}\n",
+            file_move.from, file_move.to
+        ));
+    }
     result.pop();
     Ok(result)
 }
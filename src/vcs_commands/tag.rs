@@ -0,0 +1,124 @@
+use crate::util::rebase::ensure_no_rebase_in_progress;
+use crate::util::revision::{check_ref_name, resolve_revision};
+use crate::util::tag::{current_user, Tag, TagAnnotation};
+use crate::util::vcs_state::VcsState;
+use chrono::Local;
+use indoc::indoc;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Lists the tags sorted by name
+pub fn list(repos_root: &Path) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    if state.tags.is_empty() {
+        return Ok("No tags".to_owned());
+    }
+    let mut tags: Vec<&Tag> = state.tags.iter().collect();
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+    let width = tags.iter().map(|x| x.name.len()).max().unwrap_or(0);
+    Ok(tags
+        .iter()
+        .map(|tag| match &tag.annotation {
+            Some(annotation) => format!(
+                "{:<width$} {} {}",
+                tag.name,
+                tag.commit_hash.short_str(),
+                annotation.message
+            ),
+            None => format!("{:<width$} {}", tag.name, tag.commit_hash.short_str()),
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Creates a tag pointing to the given revision, annotated if the message is provided
+pub fn create(
+    repos_root: &Path,
+    name: &str,
+    revision: &Option<String>,
+    message: &Option<String>,
+) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    check_ref_name(name, "tag")?;
+    // branches are resolved before tags, such a tag could never be used
+    if state.branches.iter().any(|x| x.name == name) {
+        return Err(Error::other(format!(
+            indoc! {
+            "There is a branch named {}, the tag would be shadowed by it.
+            Aborting..."},
+            name
+        )));
+    }
+    if state.tags.iter().any(|x| x.name == name) {
+        return Err(Error::other(format!(
+            indoc! {
+            "Tag {} already exists.
+            Aborting..."},
+            name
+        )));
+    }
+    let commit_hash = resolve_revision(&state, revision.as_deref().unwrap_or("HEAD"))?;
+    let annotation = message.as_ref().map(|message| TagAnnotation {
+        tagger: current_user(),
+        time: Local::now(),
+        message: message.clone(),
+    });
+    let result = format!(
+        "Created {}tag {} at commit {}",
+        if annotation.is_some() {
+            "annotated "
+        } else {
+            ""
+        },
+        name,
+        commit_hash.short_str()
+    );
+    state.tags.push(Tag {
+        name: name.to_owned(),
+        commit_hash,
+        annotation,
+    });
+    state.update_vcs_dir(repos_root)?;
+    Ok(result)
+}
+
+/// Shows the tag with its annotation
+pub fn show(repos_root: &Path, name: &str) -> Result<String, Error> {
+    let state = VcsState::load(repos_root)?;
+    let tag = find_tag(&state, name)?;
+    let mut result = format!("tag {}\n", tag.name);
+    if let Some(annotation) = &tag.annotation {
+        result.push_str(&format!(
+            indoc! {"
+            Tagger: {}
+            Date: {}
+            Message: {}
+            "},
+            annotation.tagger,
+            annotation.time.format("%a %b %-e %X %Y %z"),
+            annotation.message
+        ));
+    }
+    result.push_str(&format!("commit {}", tag.commit_hash));
+    Ok(result)
+}
+
+/// Deletes the tag, the commit is kept
+pub fn delete(repos_root: &Path, name: &str) -> Result<String, Error> {
+    ensure_no_rebase_in_progress(repos_root)?;
+    let mut state = VcsState::load(repos_root)?;
+    let hash = find_tag(&state, name)?.commit_hash;
+    state.tags.retain(|x| x.name != name);
+    state.update_vcs_dir(repos_root)?;
+    Ok(format!("Deleted tag {} (was {})", name, hash.short_str()))
+}
+
+fn find_tag<'a>(state: &'a VcsState, name: &str) -> Result<&'a Tag, Error> {
+    state.tags.iter().find(|x| x.name == name).ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("error: tag {} does not exist", name),
+        )
+    })
+}
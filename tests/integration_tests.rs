@@ -2,9 +2,17 @@ use std::env::current_dir;
 use std::fs::{create_dir, remove_dir_all, write};
 use std::io::Error;
 use std::path::Path;
+use vcs::util::diff::{ConflictStyle, Favor};
+use vcs::util::graph::history;
+use vcs::util::merge::MergeKind;
+use vcs::util::rename::DEFAULT_RENAME_THRESHOLD;
+use vcs::util::stash::Stash;
 use vcs::util::vcs_state::VcsState;
+use vcs::vcs_commands::merge::{FastForward, MergeOptions, Strategy};
 use vcs::vcs_commands::*;
 
+const RENAMES: Option<u8> = Some(DEFAULT_RENAME_THRESHOLD);
+
 fn fill_directory(path: &Path) -> Result<(), Error> {
     write(path.join("1.txt"), "1")?;
     let inner = path.join("inner");
@@ -31,7 +39,7 @@ fn add_contents(path: &Path) -> Result<(), Error> {
 #[test]
 fn test_init1() -> Result<(), Error> {
     let repos_str = "test_init1";
-    let repos_path = current_dir()?.join(&repos_str);
+    let repos_path = current_dir()?.join(repos_str);
     assert!(!repos_path.try_exists()?, "{:?}", repos_path);
 
     create_dir(&repos_path)?;
@@ -45,7 +53,7 @@ fn test_init1() -> Result<(), Error> {
 #[test]
 fn test_init2() -> Result<(), Error> {
     let repos_str = "test_init2";
-    let repos_path = current_dir()?.join(&repos_str);
+    let repos_path = current_dir()?.join(repos_str);
     assert!(!repos_path.try_exists()?, "{:?}", repos_path);
 
     create_dir(&repos_path)?;
@@ -60,17 +68,17 @@ fn test_init2() -> Result<(), Error> {
 #[test]
 fn test_status() -> Result<(), Error> {
     let repos_str = "test_status";
-    let repos_path = current_dir()?.join(&repos_str);
+    let repos_path = current_dir()?.join(repos_str);
     assert!(!repos_path.try_exists()?, "{:?}", repos_path);
 
     assert!(init::run(&repos_path).is_ok());
     VcsState::assert_validity(&repos_path);
 
     const NO_CHANGES: &str = "No changes to be committed";
-    assert_eq!(status::run(&repos_path).ok().unwrap(), NO_CHANGES);
+    assert_eq!(status::run(&repos_path, RENAMES).ok().unwrap(), NO_CHANGES);
     VcsState::assert_validity(&repos_path);
     assert!(fill_directory(&repos_path).is_ok());
-    assert_ne!(status::run(&repos_path).ok().unwrap(), NO_CHANGES);
+    assert_ne!(status::run(&repos_path, RENAMES).ok().unwrap(), NO_CHANGES);
     VcsState::assert_validity(&repos_path);
 
     remove_dir_all(&repos_path)?;
@@ -80,25 +88,25 @@ fn test_status() -> Result<(), Error> {
 #[test]
 fn test_complex() -> Result<(), Error> {
     let repos_str = "test_complex1";
-    let repos_path = current_dir()?.join(&repos_str);
+    let repos_path = current_dir()?.join(repos_str);
     assert!(!repos_path.try_exists()?, "{:?}", repos_path);
 
     assert!(init::run(&repos_path).is_ok());
     VcsState::assert_validity(&repos_path);
-    assert!(commit::run(&repos_path, "hi").is_err());
+    assert!(commit::run(&repos_path, "hi", RENAMES).is_err());
     VcsState::assert_validity(&repos_path);
     assert!(fill_directory(&repos_path).is_ok());
 
-    let init_hash = new_branch::run(&repos_path, "dev").ok().unwrap();
+    let init_hash = new_branch::run(&repos_path, "dev", &None).ok().unwrap();
     let init_hash = &init_hash[(init_hash.len() - 6)..];
 
     VcsState::assert_validity(&repos_path);
-    assert!(new_branch::run(&repos_path, "dev").is_err());
+    assert!(new_branch::run(&repos_path, "dev", &None).is_err());
     VcsState::assert_validity(&repos_path);
 
     assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_err());
 
-    assert!(commit::run(&repos_path, "hi").is_ok());
+    assert!(commit::run(&repos_path, "hi", RENAMES).is_ok());
     VcsState::assert_validity(&repos_path);
 
     assert!(jump::run(&repos_path, &None, &None).is_err());
@@ -117,17 +125,1605 @@ fn test_complex() -> Result<(), Error> {
     VcsState::assert_validity(&repos_path);
 
     assert!(change_contents(&repos_path).is_ok());
-    assert!(merge::run(&repos_path, "dev").is_err());
+    assert!(merge::run(&repos_path, "dev", &MergeOptions::default()).is_err());
     VcsState::assert_validity(&repos_path);
     assert!(restore_contents(&repos_path).is_ok());
     assert!(add_contents(&repos_path).is_ok());
-    assert!(merge::run(&repos_path, "dev").is_err());
+    assert!(merge::run(&repos_path, "dev", &MergeOptions::default()).is_err());
+    VcsState::assert_validity(&repos_path);
+    assert!(commit::run(&repos_path, "hi2", RENAMES).is_ok());
+    VcsState::assert_validity(&repos_path);
+    assert!(merge::run(&repos_path, "dev", &MergeOptions::default()).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_blame() -> Result<(), Error> {
+    let repos_str = "test_blame";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    assert!(init::run(&repos_path).is_ok());
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    assert!(commit::run(&repos_path, "first", RENAMES).is_ok());
+    let first = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "a\nB\nc\nd\n")?;
+    assert!(commit::run(&repos_path, "second", RENAMES).is_ok());
+    let second = VcsState::load(&repos_path)?.head;
+    VcsState::assert_validity(&repos_path);
+
+    let output = blame::run(&repos_path, "1.txt", &None, &None)?;
+    let owners: Vec<&str> = output.lines().map(|x| &x[..6]).collect();
+    assert_eq!(
+        owners,
+        [
+            first.short_str(),
+            second.short_str(),
+            first.short_str(),
+            second.short_str()
+        ]
+    );
+    assert!(output.lines().nth(1).unwrap().ends_with("2) B"));
+
+    let output = blame::run(&repos_path, "1.txt", &None, &Some((2, 3)))?;
+    assert_eq!(output.lines().count(), 2);
+    assert!(blame::run(&repos_path, "1.txt", &None, &Some((3, 5))).is_err());
+
+    let mut output = vec![];
+    blame::incremental(
+        &repos_path,
+        "1.txt",
+        &Some(first.short_str()),
+        &None,
+        &mut output,
+    )?;
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with(&format!("{} 1 1 3\n", first)));
+    assert!(output.ends_with("filename 1.txt\n"));
+    assert!(blame::run(&repos_path, "2.txt", &None, &None).is_err());
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_restore() -> Result<(), Error> {
+    let repos_str = "test_restore";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    let init_hash = VcsState::load(&repos_path)?.head;
+
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(add_contents(&repos_path).is_ok());
+    assert!(restore::run(&repos_path, &["missing".to_owned()], &None, false).is_err());
+    assert!(restore::run(&repos_path, &["1.txt".to_owned()], &None, false).is_ok());
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "1");
+    assert!(repos_path.join("3.txt").try_exists()?);
+    assert!(restore::run(&repos_path, &[], &None, true).is_ok());
+    assert!(!repos_path.join("3.txt").try_exists()?);
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+
+    write(repos_path.join("inner").join("2.txt"), "22")?;
+    assert!(commit::run(&repos_path, "change 2", RENAMES).is_ok());
+    assert!(restore::run(
+        &repos_path,
+        &["inner".to_owned()],
+        &Some(init_hash.to_string()),
+        false
+    )
+    .is_ok());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("inner").join("2.txt"))?,
+        "2"
+    );
+    assert_ne!(VcsState::load(&repos_path)?.head, init_hash);
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_stash() -> Result<(), Error> {
+    let repos_str = "test_stash";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    assert!(stash::push(&repos_path, &None, RENAMES).is_err());
+
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(stash::push(&repos_path, &Some("wip".to_owned()), RENAMES).is_ok());
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert_eq!(stash::list(&repos_path)?, "stash@{0}: On master: wip");
+    assert!(stash::show(&repos_path, 0)?.ends_with("modified: 1.txt"));
+
+    // the stash is applied on top of a different commit
+    write(repos_path.join("inner").join("2.txt"), "22")?;
+    assert!(commit::run(&repos_path, "change 2", RENAMES).is_ok());
+    assert!(stash::apply(&repos_path, 0, true)?.starts_with("Applied"));
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "3");
+    assert_eq!(stash::list(&repos_path)?, "No stash entries");
+    assert!(stash::drop(&repos_path, 0).is_err());
+
+    assert!(stash::push(&repos_path, &None, RENAMES).is_ok());
+    write(repos_path.join("1.txt"), "4")?;
+    assert!(commit::run(&repos_path, "change 1", RENAMES).is_ok());
+    assert!(stash::apply(&repos_path, 0, true)?.starts_with("Conflict"));
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "4");
+    assert!(stash::drop(&repos_path, 0).is_ok());
+
+    // a commit with the tree of a stash entry gets its own snapshot
+    write(repos_path.join("1.txt"), "5")?;
+    assert!(stash::push(&repos_path, &None, RENAMES).is_ok());
+    assert!(stash::apply(&repos_path, 0, false)?.starts_with("Applied"));
+    assert!(commit::run(&repos_path, "change 1 again", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+    assert!(Stash::load(&repos_path)?.entries[0].hash != head);
+    assert!(stash::drop(&repos_path, 0).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_tag() -> Result<(), Error> {
+    let repos_str = "test_tag";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    let init_hash = VcsState::load(&repos_path)?.head;
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "change 1", RENAMES).is_ok());
+
+    assert_eq!(tag::list(&repos_path)?, "No tags");
+    assert!(tag::create(&repos_path, "v1", &None, &None).is_ok());
+    assert!(tag::create(&repos_path, "v1", &None, &None).is_err());
+    assert!(tag::create(&repos_path, "v@{1}", &None, &None).is_err());
+    assert!(tag::create(&repos_path, "master", &None, &None).is_err());
+    assert!(tag::create(
+        &repos_path,
+        "v0",
+        &Some(init_hash.short_str()),
+        &Some("first release".to_owned())
+    )
+    .is_ok());
+    assert!(tag::show(&repos_path, "v0")?.contains("Message: first release"));
+    assert!(tag::list(&repos_path)?.starts_with("v0 "));
+    assert!(log::run(&repos_path, false)?.contains("(HEAD -> master, tag: v1)"));
+    VcsState::assert_validity(&repos_path);
+
+    assert!(restore::run(&repos_path, &[], &Some("v0".to_owned()), false).is_ok());
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "1");
+    assert!(restore::run(&repos_path, &[], &Some("v1".to_owned()), false).is_ok());
+    assert!(jump::run(&repos_path, &None, &Some("v0".to_owned())).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, init_hash);
+    assert!(gc::run(&repos_path)?.starts_with("Removed 0 unreachable commits"));
+    VcsState::assert_validity(&repos_path);
+
+    assert!(tag::delete(&repos_path, "v0").is_ok());
+    assert!(tag::delete(&repos_path, "v0").is_err());
+    assert!(restore::run(&repos_path, &[], &Some("v0".to_owned()), false).is_err());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_branch() -> Result<(), Error> {
+    let repos_str = "test_branch";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "dev", &None).is_ok());
+    assert!(branch::describe(&repos_path, None, "work in progress").is_ok());
+    let output = branch::list(&repos_path)?;
+    assert!(output.contains("* dev"));
+    assert!(output.ends_with("work in progress"));
+
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "change 1", RENAMES).is_ok());
+    assert!(branch::rename(&repos_path, Some("master"), "main").is_err());
+    assert!(branch::rename(&repos_path, None, "HEAD").is_err());
+    assert!(branch::rename(&repos_path, None, "feature").is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.branch_name, "feature");
+    assert!(state.commits.iter().all(|x| x.branch_name != "dev"));
+    VcsState::assert_validity(&repos_path);
+
+    assert!(branch::delete(&repos_path, "feature", false).is_err());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(branch::delete(&repos_path, "master", true).is_err());
+    assert!(branch::delete(&repos_path, "feature", false).is_err());
+    let hash_feature = VcsState::load(&repos_path)?.branches[1].commit_hash;
+    assert!(branch::delete(&repos_path, "feature", true).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.branches.len(), 1);
+    let output = reflog::run(&repos_path, &Some("feature".to_owned()))?;
+    assert!(output.starts_with(&format!(
+        "{} feature@{{0}}: branch: Deleted branch feature",
+        hash_feature.short_str()
+    )));
+    VcsState::assert_validity(&repos_path);
+    // the commit of the deleted branch is still in the reflog of HEAD
+    assert!(gc::run(&repos_path)?.starts_with("Removed 0 unreachable commits"));
+    assert!(reflog::expire(&repos_path, &None).is_ok());
+    assert!(gc::run(&repos_path)?.starts_with("Removed 1 unreachable commits"));
+    VcsState::assert_validity(&repos_path);
+
+    assert!(new_branch::run(&repos_path, "merged", &None).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(branch::delete(&repos_path, "merged", false).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_new_branch_from() -> Result<(), Error> {
+    let repos_str = "test_new_branch_from";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    let init_hash = VcsState::load(&repos_path)?.head;
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+
+    assert!(new_branch::run(&repos_path, "release", &None).is_ok());
+    write(repos_path.join("inner").join("2.txt"), "release")?;
+    assert!(commit::run(&repos_path, "release change", RENAMES).is_ok());
+    // a feature branch off the release branch
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    assert!(add_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    // a branch off a historical commit
+    assert!(
+        new_branch::run(&repos_path, "hotfix", &Some(init_hash.short_str()))?
+            .ends_with(&init_hash.short_str())
+    );
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "1");
+    assert!(!repos_path.join("3.txt").try_exists()?);
+    assert_eq!(log::run(&repos_path, false)?.matches("commit ").count(), 1);
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default()).is_ok());
+    assert!(repos_path.join("3.txt").try_exists()?);
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("inner").join("2.txt"))?,
+        "release"
+    );
+    // commits of the release branch are kept since it still exists
+    assert!(jump::run(&repos_path, &Some("release".to_owned()), &None).is_ok());
+    assert_eq!(log::run(&repos_path, false)?.matches("commit ").count(), 3);
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_into_branch() -> Result<(), Error> {
+    let repos_str = "test_merge_into_branch";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("inner").join("2.txt"), "feature")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default()).is_err());
+    assert!(merge::run(&repos_path, "master", &MergeOptions::default())?
+        .starts_with("Already up to date"));
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+
+    // keep the feature branch up to date with master
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    assert!(
+        merge::run(&repos_path, "master", &MergeOptions::default())?.starts_with("Successfully")
+    );
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "3");
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("inner").join("2.txt"))?,
+        "feature"
+    );
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.branches.len(), 2);
+    assert_eq!(state.branch_name, "feature");
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default())?.contains("Fast-forward"));
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("inner").join("2.txt"))?,
+        "feature"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_commit() -> Result<(), Error> {
+    let repos_str = "test_merge_commit";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    assert!(fill_directory(&repos_path).is_ok());
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    assert!(add_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    let feature_hash = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(change_contents(&repos_path).is_ok());
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+    let master_hash = VcsState::load(&repos_path)?.head;
+
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default()).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    // unwrap: the head commit always exists
+    let merge_commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(merge_commit.parents, vec![master_hash, feature_hash]);
+    assert!(state.find_commit(&feature_hash).is_some());
+    assert_eq!(state.branches.len(), 2);
+    VcsState::assert_validity(&repos_path);
+
+    let output = log::run(&repos_path, false)?;
+    assert_eq!(output.matches("commit ").count(), 4);
+    assert!(output.contains(&format!(
+        "Merge: {} {}",
+        master_hash.short_str(),
+        feature_hash.short_str()
+    )));
+    assert!(output.contains("feature change"));
+    let output = log::run(&repos_path, true)?;
+    assert_eq!(output.matches("commit ").count(), 3);
+    assert!(!output.contains("feature change"));
+
+    // the branch is merged now, so it can be deleted safely
+    assert!(branch::delete(&repos_path, "feature", false).is_ok());
+    assert!(gc::run(&repos_path)?.starts_with("Removed 0 unreachable commits"));
+    assert!(blame::run(&repos_path, "3.txt", &None, &None)?.starts_with(&feature_hash.short_str()));
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_lines() -> Result<(), Error> {
+    let repos_str = "test_merge_lines";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\nd\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "A\nb\nc\nd\n")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "a\nb\nc\nD\n")?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+
+    let output = merge::run(&repos_path, "feature", &MergeOptions::default())?;
+    assert!(output.contains("Auto-merged 1.txt"));
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "A\nb\nc\nD\n"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "A\nfeature\nc\nd\n")?;
+    assert!(commit::run(&repos_path, "feature conflict", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "A\nmaster\nc\nD\n")?;
+    assert!(commit::run(&repos_path, "master conflict", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+
+    let options = MergeOptions {
+        conflict_style: ConflictStyle::Diff3,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &options)?.starts_with("Merge conflict"));
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "A\n<<<<<<< master\nmaster\n||||||| base\nb\n=======\nfeature\n>>>>>>> feature\nc\nD\n"
+    );
+    assert_eq!(VcsState::load(&repos_path)?.head, head);
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_continue() -> Result<(), Error> {
+    let repos_str = "test_merge_continue";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "feature\n")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    let hash_feature = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "master\n")?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_err());
+    assert!(merge::abort(&repos_path, MergeKind::Merge).is_err());
+
+    let output = merge::run(&repos_path, "feature", &MergeOptions::default())?;
+    assert!(output.contains("merge --continue"));
+    assert!(status::run(&repos_path, RENAMES)?.contains("both modified: 1.txt"));
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default()).is_err());
+    assert!(commit::run(&repos_path, "too early", RENAMES).is_err());
+    assert!(merge::abort(&repos_path, MergeKind::Merge).is_ok());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "master\n"
+    );
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default()).is_ok());
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_err());
+    write(repos_path.join("1.txt"), "resolved\n")?;
+    assert!(status::run(&repos_path, RENAMES)?.contains("All conflicts fixed"));
+    let output = merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES)?;
+    assert!(output.starts_with("Successfully created merge commit"));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.parents, vec![head, hash_feature]);
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_resolve() -> Result<(), Error> {
+    let repos_str = "test_merge_resolve";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\n")?;
+    write(repos_path.join("2.bin"), [0xff, 0])?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    std::fs::remove_file(repos_path.join("1.txt"))?;
+    write(repos_path.join("2.bin"), [0xff, 1])?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "master\n")?;
+    write(repos_path.join("2.bin"), [0xff, 2])?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+
+    // modify/delete and binary conflicts hold no markers, they are resolved explicitly
+    let output = merge::run(&repos_path, "feature", &MergeOptions::default())?;
+    assert!(output.contains("  1.txt\n  2.bin\n"));
+    let output = status::run(&repos_path, RENAMES)?;
+    assert!(output.contains("both modified: 1.txt"));
+    assert!(output.contains("both modified: 2.bin"));
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_err());
+    assert!(resolve::run(&repos_path, &["3.txt".to_owned()]).is_err());
+    assert_eq!(
+        resolve::run(&repos_path, &["1.txt".to_owned()])?,
+        "Resolved 1.txt"
+    );
+    assert!(!status::run(&repos_path, RENAMES)?.contains("both modified: 1.txt"));
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_err());
+    assert!(checkout::run(&repos_path, &["2.bin".to_owned()], true).is_ok());
+    assert!(status::run(&repos_path, RENAMES)?.contains("All conflicts fixed"));
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_ok());
+    assert_eq!(std::fs::read(repos_path.join("2.bin"))?, [0xff, 1]);
+    assert!(resolve::run(&repos_path, &[]).is_err());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_fast_forward() -> Result<(), Error> {
+    let repos_str = "test_merge_fast_forward";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    let hash_feature = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    let commits_count = VcsState::load(&repos_path)?.commits.len();
+
+    let ff_only = MergeOptions {
+        fast_forward: FastForward::Only,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &ff_only)?.ends_with("Fast-forward"));
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.head, hash_feature);
+    assert_eq!(state.branches[0].commit_hash, hash_feature);
+    assert_eq!(state.commits.len(), commits_count);
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "b");
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    write(repos_path.join("2.txt"), "feature")?;
+    assert!(commit::run(&repos_path, "feature file", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    let no_ff = MergeOptions {
+        fast_forward: FastForward::Never,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &no_ff)?.starts_with("Successfully"));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.parents.len(), 2);
+
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    write(repos_path.join("3.txt"), "feature")?;
+    assert!(commit::run(&repos_path, "diverged", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(merge::run(&repos_path, "feature", &ff_only).is_err());
+    assert!(!repos_path.join("3.txt").exists());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_squash() -> Result<(), Error> {
+    let repos_str = "test_merge_squash";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "first step", RENAMES).is_ok());
+    write(repos_path.join("2.txt"), "c")?;
+    assert!(commit::run(&repos_path, "second step", RENAMES).is_ok());
+    let hash_feature = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    let hash_master = VcsState::load(&repos_path)?.head;
+
+    let options = MergeOptions {
+        squash: true,
+        ..Default::default()
+    };
+    let output = merge::run(&repos_path, "feature", &options)?;
+    assert!(output.starts_with("Successfully created squash commit"));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.parents, vec![hash_master]);
+    assert!(commit
+        .message
+        .starts_with("Squashed commits of branch feature:"));
+    assert!(commit.message.contains("first step"));
+    assert!(commit.message.contains("second step"));
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "b");
+    assert_eq!(std::fs::read_to_string(repos_path.join("2.txt"))?, "c");
+    assert!(state
+        .branches
+        .iter()
+        .any(|x| x.name == "feature" && x.commit_hash == hash_feature));
+    VcsState::assert_validity(&repos_path);
+
+    // a conflicting squash takes theirs from the branch
+    write(repos_path.join("1.txt"), "master")?;
+    assert!(commit::run(&repos_path, "master step", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "feature")?;
+    assert!(commit::run(&repos_path, "third step", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(merge::run(&repos_path, "feature", &options)?.contains("Merge conflict"));
+    assert_eq!(
+        checkout::run(&repos_path, &["1.txt".to_owned()], true)?,
+        "Took theirs version of 1.txt"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "feature"
+    );
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_strategies() -> Result<(), Error> {
+    let repos_str = "test_merge_strategies";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "A\nb\nfeature\n")?;
+    write(repos_path.join("2.txt"), "feature")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "a\nb\nmaster\n")?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+
+    let ours = MergeOptions {
+        strategy: Strategy::Ours,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &ours)?.starts_with("Successfully"));
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.find_commit(&state.head).unwrap().parents.len(), 2);
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "a\nb\nmaster\n"
+    );
+    assert!(!repos_path.join("2.txt").exists());
+    VcsState::assert_validity(&repos_path);
+
+    assert!(new_branch::run(&repos_path, "theirs", &Some(head.to_string())).is_ok());
+    let theirs = MergeOptions {
+        favor: Favor::Theirs,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &theirs)?.contains("Auto-merged 1.txt"));
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "A\nb\nfeature\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("2.txt"))?,
+        "feature"
+    );
+
+    assert!(new_branch::run(&repos_path, "manual", &Some(head.to_string())).is_ok());
+    assert!(checkout::run(&repos_path, &[], true).is_err());
+    assert!(merge::run(&repos_path, "feature", &MergeOptions::default())?.contains("1.txt"));
+    assert!(checkout::run(&repos_path, &["2.txt".to_owned()], true).is_err());
+    assert!(checkout::run(&repos_path, &["1.txt".to_owned()], false).is_ok());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "a\nb\nmaster\n"
+    );
+    assert!(checkout::run(&repos_path, &[], true).is_ok());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "A\nb\nfeature\n"
+    );
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_ok());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_renames() -> Result<(), Error> {
+    let repos_str = "test_renames";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\nd\n")?;
+    write(repos_path.join("2.txt"), "x\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    create_dir(repos_path.join("inner"))?;
+    std::fs::rename(
+        repos_path.join("1.txt"),
+        repos_path.join("inner").join("1.txt"),
+    )?;
+    write(repos_path.join("inner").join("1.txt"), "a\nb\nc\nD\n")?;
+    assert!(status::run(&repos_path, RENAMES)?.contains("renamed:  1.txt -> inner/1.txt"));
+    assert!(!status::run(&repos_path, Some(80))?.contains("renamed:"));
+    assert!(status::run(&repos_path, None)?.contains("deleted:"));
+    let output = commit::run(&repos_path, "move", RENAMES)?;
+    assert!(output.contains("1 file renamed"));
+    assert!(log::run(&repos_path, false)?.contains("renamed 1.txt -> inner/1.txt (75%)"));
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "A\nb\nc\nd\n")?;
+    assert!(commit::run(&repos_path, "edit", RENAMES).is_ok());
+    let output = merge::run(&repos_path, "feature", &MergeOptions::default())?;
+    assert!(output.starts_with("Auto-merged inner/1.txt"), "{}", output);
+    assert!(!repos_path.join("1.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("inner").join("1.txt"))?,
+        "A\nb\nc\nD\n"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_dry_run() -> Result<(), Error> {
+    let repos_str = "test_merge_dry_run";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    write(repos_path.join("2.txt"), "a\nb\nc\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_base = VcsState::load(&repos_path)?.head;
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "feature\nb\nc\n")?;
+    write(repos_path.join("2.txt"), "feature\nb\nc\n")?;
+    write(repos_path.join("3.txt"), "new")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "master\nb\nc\n")?;
+    write(repos_path.join("2.txt"), "a\nb\nmaster\n")?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+
+    let status_json = std::fs::read(repos_path.join(".vcs").join("status.json"))?;
+    let options = MergeOptions {
+        dry_run: true,
+        ..Default::default()
+    };
+    let output = merge::run(&repos_path, "feature", &options)?;
+    assert_eq!(
+        output,
+        format!(
+            "Merge base: {}\n\
+            Files that would change:\n  1.txt\n  2.txt\n  3.txt\n\
+            Files that would be auto-merged:\n  2.txt\n\
+            Files that would conflict:\n  1.txt",
+            hash_base.short_str()
+        )
+    );
+    assert_eq!(
+        std::fs::read(repos_path.join(".vcs").join("status.json"))?,
+        status_json
+    );
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert!(merge::abort(&repos_path, MergeKind::Merge).is_err());
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_octopus() -> Result<(), Error> {
+    let repos_str = "test_merge_octopus";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    let mut hashes = vec![];
+    for (name, file, contents) in [
+        ("a", "a.txt", "a"),
+        ("b", "b.txt", "b"),
+        ("c", "1.txt", "a\nb\nc\nd\n"),
+    ] {
+        assert!(new_branch::run(&repos_path, name, &Some("master".to_owned())).is_ok());
+        write(repos_path.join(file), contents)?;
+        assert!(commit::run(&repos_path, name, RENAMES).is_ok());
+        hashes.push(VcsState::load(&repos_path)?.head);
+    }
+    assert!(new_branch::run(&repos_path, "d", &Some("master".to_owned())).is_ok());
+    write(repos_path.join("1.txt"), "x\nb\nc\n")?;
+    assert!(commit::run(&repos_path, "d", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "y\nb\nc\n")?;
+    assert!(commit::run(&repos_path, "master change", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+
+    let branches = |names: &[&str]| names.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    let output = merge::octopus(
+        &repos_path,
+        &branches(&["a", "c", "d"]),
+        &MergeOptions::default(),
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(output.contains("branch d conflicts with the merged result in\n  1.txt"));
+    assert_eq!(VcsState::load(&repos_path)?.head, head);
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+
+    let output = merge::octopus(
+        &repos_path,
+        &branches(&["a", "b", "c"]),
+        &MergeOptions::default(),
+    )?;
+    assert!(output.contains("Auto-merged 1.txt"));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.parents, [vec![head], hashes].concat());
+    assert_eq!(commit.message, "Merged branches a, b, c into master.");
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "y\nb\nc\nd\n"
+    );
+    assert!(repos_path.join("a.txt").exists() && repos_path.join("b.txt").exists());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_rebase() -> Result<(), Error> {
+    let repos_str = "test_rebase";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("2.txt"), "feature\n")?;
+    assert!(commit::run(&repos_path, "feature file", RENAMES).is_ok());
+    write(repos_path.join("1.txt"), "feature\n")?;
+    assert!(commit::run(&repos_path, "feature edit", RENAMES).is_ok());
+    let orig_head = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "master\n")?;
+    assert!(commit::run(&repos_path, "master edit", RENAMES).is_ok());
+    let hash_master = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+
+    let output = rebase::run(&repos_path, "master", RENAMES)?;
+    assert!(output.contains("Could not apply"));
+    assert!(output.contains("feature edit\n  1.txt"));
+    assert!(
+        status::run(&repos_path, RENAMES)?.contains("You are currently rebasing branch feature")
+    );
+    assert!(commit::run(&repos_path, "too early", RENAMES).is_err());
+    assert!(new_branch::run(&repos_path, "too_early", &None).is_err());
+    assert!(tag::create(&repos_path, "too_early", &None, &None).is_err());
+    assert!(rebase::abort(&repos_path).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.head, orig_head);
+    assert_eq!(state.branches[1].commit_hash, orig_head);
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "feature\n"
+    );
+
+    assert!(rebase::run(&repos_path, "master", RENAMES)?.contains("Could not apply"));
+    assert!(rebase::continue_rebase(&repos_path, RENAMES).is_err());
+    write(repos_path.join("1.txt"), "master and feature\n")?;
+    let output = rebase::continue_rebase(&repos_path, RENAMES)?;
+    assert!(output.ends_with(&format!(
+        "Successfully rebased branch feature onto {}",
+        hash_master.short_str()
+    )));
+    let state = VcsState::load(&repos_path)?;
+    let log: Vec<&str> = history(&state, &[state.head], false)
+        .iter()
+        .map(|x| state.find_commit(x).unwrap().message.as_str())
+        .collect();
+    assert_eq!(
+        log,
+        [
+            "feature edit",
+            "feature file",
+            "master edit",
+            "Initial commit"
+        ]
+    );
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("2.txt"))?,
+        "feature\n"
+    );
+    assert!(rebase::skip(&repos_path, RENAMES).is_err());
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("3.txt"), "master\n")?;
+    assert!(commit::run(&repos_path, "master file", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    let output = rebase::run(&repos_path, "master", RENAMES)?;
+    assert_eq!(output.matches("Applied").count(), 2);
+    assert!(repos_path.join("3.txt").exists());
+    assert!(rebase::run(&repos_path, "master", RENAMES)?.contains("is up to date"));
+    VcsState::assert_validity(&repos_path);
+
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("4.txt"), "master\n")?;
+    assert!(commit::run(&repos_path, "master file 4", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("feature".to_owned()), &None).is_ok());
+    assert!(merge::run(&repos_path, "master", &MergeOptions::default()).is_ok());
+    let output = rebase::run(&repos_path, "master", RENAMES)?;
+    assert!(output.starts_with("Skipped merge commit"));
+    assert!(output.contains("is up to date"));
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_rebase_interactive() -> Result<(), Error> {
+    let repos_str = "test_rebase_interactive";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    let mut hashes = vec![];
+    for (file, contents, message) in [
+        ("2.txt", "feature\n", "add file"),
+        ("2.txt", "feature fixed\n", "fixup! add file"),
+        ("3.txt", "debug\n", "debug output"),
+        ("4.txt", "more\n", "more work"),
+    ] {
+        write(repos_path.join(file), contents)?;
+        assert!(commit::run(&repos_path, message, RENAMES).is_ok());
+        hashes.push(VcsState::load(&repos_path)?.head.short_str());
+    }
+    let todo_path = repos_path.join(".vcs").join("todo.txt");
+    write(
+        &todo_path,
+        format!(
+            "pick {} add file\nfixup {}\n# comment\ndrop {}\nedit {}\n",
+            hashes[0], hashes[1], hashes[2], hashes[3]
+        ),
+    )?;
+
+    let output = rebase::interactive(&repos_path, "master", &Some(todo_path.clone()), RENAMES)?;
+    assert!(output.contains("Stopped at"), "{}", output);
+    assert!(status::run(&repos_path, RENAMES)?.contains("Stopped to edit"));
+    write(repos_path.join("4.txt"), "more edited\n")?;
+    let output = rebase::continue_rebase(&repos_path, RENAMES)?;
+    assert!(output.contains("Amended"));
+    assert!(output.contains("Successfully rebased branch feature"));
+
+    let state = VcsState::load(&repos_path)?;
+    let log: Vec<&str> = history(&state, &[state.head], false)
+        .iter()
+        .map(|x| state.find_commit(x).unwrap().message.as_str())
+        .collect();
+    assert_eq!(log, ["more work", "add file", "Initial commit"]);
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("2.txt"))?,
+        "feature fixed\n"
+    );
+    assert!(!repos_path.join("3.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("4.txt"))?,
+        "more edited\n"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    let head = state.head.short_str();
+    write(&todo_path, format!("pick {}\n", hashes[2]))?;
+    assert!(rebase::interactive(&repos_path, "master", &Some(todo_path.clone()), RENAMES).is_err());
+    write(&todo_path, format!("squash {}\n", head))?;
+    assert!(rebase::interactive(&repos_path, &head, &Some(todo_path.clone()), RENAMES).is_err());
+    let parent = state.find_commit(&state.head).unwrap().parents[0].short_str();
+    write(&todo_path, format!("reword {} finished work\n", head))?;
+    assert!(rebase::interactive(&repos_path, &parent, &Some(todo_path), RENAMES).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(
+        state.find_commit(&state.head).unwrap().message,
+        "finished work"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_cherry_pick_revert() -> Result<(), Error> {
+    let repos_str = "test_cherry_pick_revert";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("1.txt"), "a\nb\nfeature\n")?;
+    assert!(commit::run(&repos_path, "feature edit", RENAMES).is_ok());
+    let hash_edit = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("2.txt"), "feature\n")?;
+    assert!(commit::run(&repos_path, "feature file", RENAMES).is_ok());
+    let hash_file = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    write(repos_path.join("1.txt"), "master\nb\nc\n")?;
+    assert!(commit::run(&repos_path, "master edit", RENAMES).is_ok());
+    let head = VcsState::load(&repos_path)?.head;
+
+    let output = cherry_pick::run(
+        &repos_path,
+        &[hash_file.to_string(), hash_edit.to_string()],
+        RENAMES,
+    )?;
+    assert!(output.contains("feature file") && output.contains("feature edit"));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.message, "feature edit");
+    assert_eq!(commit.parents.len(), 1);
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "master\nb\nfeature\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("2.txt"))?,
+        "feature\n"
+    );
+    assert!(
+        cherry_pick::run(&repos_path, &[hash_file.to_string()], RENAMES)?.starts_with("Skipped")
+    );
+
+    let output = revert::run(&repos_path, &[head.to_string()], RENAMES)?;
+    assert!(output.contains("Revert \"master edit\""));
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "a\nb\nfeature\n"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    write(repos_path.join("1.txt"), "x\nb\nc\n")?;
+    assert!(commit::run(&repos_path, "rewrite", RENAMES).is_ok());
+    let output = revert::run(&repos_path, &[head.to_string()], RENAMES)?;
+    assert!(output.contains("Could not revert"));
+    assert!(status::run(&repos_path, RENAMES)?.contains("both modified: 1.txt"));
+    assert!(status::run(&repos_path, RENAMES)?.contains("\"revert --continue\""));
+    assert!(merge::abort(&repos_path, MergeKind::Merge).is_err());
+    assert!(merge::abort(&repos_path, MergeKind::CherryPick).is_err());
+    assert!(merge::continue_merge(&repos_path, MergeKind::Merge, RENAMES).is_err());
+    assert!(merge::abort(&repos_path, MergeKind::Revert).is_ok());
+    assert_eq!(
+        std::fs::read_to_string(repos_path.join("1.txt"))?,
+        "x\nb\nc\n"
+    );
+    assert!(revert::run(&repos_path, &[head.to_string()], RENAMES)?.contains("Could not revert"));
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    assert!(
+        merge::continue_merge(&repos_path, MergeKind::Revert, RENAMES)?
+            .contains("Revert \"master edit\"")
+    );
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    write(repos_path.join("3.txt"), "3\n")?;
+    assert!(commit::run(&repos_path, "third", RENAMES).is_ok());
+    let hash_third = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "a\nb\nmine\n")?;
+    assert!(commit::run(&repos_path, "mine", RENAMES).is_ok());
+    let output = revert::run(
+        &repos_path,
+        &[hash_edit.to_string(), hash_third.to_string()],
+        RENAMES,
+    )?;
+    assert!(output.contains("Could not revert"));
+    assert!(output.ends_with(&format!(
+        "Applied after revert --continue: {}",
+        hash_third.short_str()
+    )));
+    write(repos_path.join("1.txt"), "a\nb\nc\n")?;
+    let output = merge::continue_merge(&repos_path, MergeKind::Revert, RENAMES)?;
+    assert!(output.contains("Revert \"feature edit\""));
+    assert!(output.contains("Revert \"third\""));
+    assert!(op::log(&repos_path)?.contains("revert --continue"));
+    assert!(!repos_path.join("3.txt").exists());
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_commit_amend() -> Result<(), Error> {
+    let repos_str = "test_commit_amend";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_init = VcsState::load(&repos_path)?.head;
+    let output = commit::amend(&repos_path, &Some("First commit".to_owned()), RENAMES)?;
+    assert!(output.ends_with(&format!("Replaced commit {}", hash_init.short_str())));
+    let hash_initial = VcsState::load(&repos_path)?.head;
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&hash_initial).unwrap();
+    assert_eq!(commit.message, "First commit");
+    assert!(commit.parents.is_empty());
+    assert_eq!(commit.changes.added, vec!["1.txt"]);
+    assert!(state.find_commit(&hash_init).is_some());
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "Fix tpyo", RENAMES).is_ok());
+    let hash_old = VcsState::load(&repos_path)?.head;
+
+    let output = commit::amend(&repos_path, &Some("Fix typo".to_owned()), RENAMES)?;
+    assert!(output.ends_with(&format!("Replaced commit {}", hash_old.short_str())));
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.message, "Fix typo");
+    assert_eq!(commit.parents, vec![hash_initial]);
+    assert_eq!(commit.changes.modified, vec!["1.txt"]);
+    assert_eq!(state.branches[0].commit_hash, state.head);
+    assert!(state.find_commit(&hash_old).is_some());
+
+    write(repos_path.join("2.txt"), "forgotten")?;
+    assert!(commit::amend(&repos_path, &None, RENAMES).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    let commit = state.find_commit(&state.head).unwrap();
+    assert_eq!(commit.message, "Fix typo");
+    assert_eq!(commit.parents, vec![hash_initial]);
+    assert_eq!(commit.changes.added, vec!["2.txt"]);
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert_eq!(
+        history(&state, &[state.head], false).len(),
+        2,
+        "the replaced commits are not in the history"
+    );
+
+    assert!(jump::run(&repos_path, &None, &Some(hash_initial.to_string())).is_ok());
+    assert!(commit::amend(&repos_path, &None, RENAMES).is_err());
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_reset() -> Result<(), Error> {
+    let repos_str = "test_reset";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_initial = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "second", RENAMES).is_ok());
+    let hash_second = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("2.txt"), "c")?;
+    assert!(commit::run(&repos_path, "third", RENAMES).is_ok());
+
+    assert!(reset::run(
+        &repos_path,
+        &hash_second.to_string(),
+        reset::ResetMode::Soft
+    )
+    .is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.head, hash_second);
+    assert_eq!(state.branches[0].commit_hash, hash_second);
+    assert!(status::run(&repos_path, RENAMES)?.contains("new file: 2.txt"));
+    assert!(commit::run(&repos_path, "third again", RENAMES).is_ok());
+
+    let output = reset::run(
+        &repos_path,
+        &hash_initial.to_string(),
+        reset::ResetMode::Hard,
+    )?;
+    assert!(output.ends_with("Initial commit"));
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "a");
+    assert!(!repos_path.join("2.txt").exists());
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert!(reset::run(&repos_path, "unknown", reset::ResetMode::Hard).is_err());
+    VcsState::assert_validity(&repos_path);
+    assert_eq!(VcsState::load(&repos_path)?.commits.len(), 4);
+    assert!(reflog::expire(&repos_path, &None).is_ok());
+    assert_eq!(
+        gc::run(&repos_path)?,
+        "Removed 3 unreachable commits and 3 snapshots"
+    );
+
+    write(repos_path.join("1.txt"), "d")?;
+    assert!(commit::run(&repos_path, "fourth", RENAMES).is_ok());
+    let hash_fourth = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "e")?;
+    assert!(commit::run(&repos_path, "fifth", RENAMES).is_ok());
+    let hash_fifth = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &None, &Some(hash_fourth.to_string())).is_ok());
+    let output = reset::run(
+        &repos_path,
+        &hash_initial.to_string(),
+        reset::ResetMode::Soft,
+    )?;
+    assert!(output.starts_with("HEAD is now at"));
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.head, hash_initial);
+    assert_eq!(state.branches[0].commit_hash, hash_fifth);
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_detached_head() -> Result<(), Error> {
+    let repos_str = "test_detached_head";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_initial = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "second", RENAMES).is_ok());
+    let hash_second = VcsState::load(&repos_path)?.head;
+
+    let output = jump::run(&repos_path, &None, &Some(hash_initial.to_string()))?;
+    assert!(output.contains("HEAD is detached"));
+    assert!(status::run(&repos_path, RENAMES)?
+        .starts_with(&format!("HEAD detached at {}", hash_initial.short_str())));
+    assert!(log::run(&repos_path, false)?.contains("(HEAD)"));
+    write(repos_path.join("2.txt"), "c")?;
+    assert!(commit::run(&repos_path, "experiment", RENAMES).is_err());
+    assert!(commit::on_new_branch(&repos_path, "experiment", "master", RENAMES).is_err());
+
+    let output = commit::on_new_branch(&repos_path, "experiment", "experiment", RENAMES)?;
+    assert!(output.starts_with("Created a new branch experiment"));
+    assert!(output.contains("[experiment "));
+    let state = VcsState::load(&repos_path)?;
+    assert!(!state.is_detached());
+    assert_eq!(
+        state.find_commit(&state.head).unwrap().parents,
+        [hash_initial]
+    );
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+
+    assert!(jump::run(&repos_path, &None, &Some(hash_initial.to_string())).is_ok());
+    write(repos_path.join("3.txt"), "d")?;
+    assert!(new_branch::run(&repos_path, "other", &None).is_ok());
+    assert!(status::run(&repos_path, RENAMES)?.contains("new file: 3.txt"));
+    assert!(commit::run(&repos_path, "other experiment", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_second);
     VcsState::assert_validity(&repos_path);
-    assert!(commit::run(&repos_path, "hi2").is_ok());
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_reflog() -> Result<(), Error> {
+    let repos_str = "test_reflog";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_initial = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "second", RENAMES).is_ok());
+    let hash_second = VcsState::load(&repos_path)?.head;
+    write(repos_path.join("1.txt"), "c")?;
+    assert!(commit::amend(&repos_path, &Some("second amended".to_owned()), RENAMES).is_ok());
+    let hash_amended = VcsState::load(&repos_path)?.head;
+    assert!(reset::run(&repos_path, "HEAD@{2}", reset::ResetMode::Hard).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_initial);
+
+    let output = reflog::run(&repos_path, &Some("master".to_owned()))?;
+    assert_eq!(output.lines().count(), 4);
+    assert!(output.starts_with(&format!(
+        "{} master@{{0}}: reset: Moving to HEAD@{{2}}",
+        hash_initial.short_str()
+    )));
+    assert!(output.contains(&format!(
+        "{} master@{{1}}: commit --amend: second amended",
+        hash_amended.short_str()
+    )));
+    assert!(reflog::run(&repos_path, &None)?.starts_with(&hash_initial.short_str()));
+    assert!(reflog::run(&repos_path, &Some("unknown".to_owned())).is_err());
+
+    // the amended and the replaced commits are both recoverable
+    assert_eq!(
+        gc::run(&repos_path)?,
+        "Removed 0 unreachable commits and 0 snapshots"
+    );
+    assert!(reset::run(&repos_path, "master@{2}", reset::ResetMode::Hard).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_second);
+    assert_eq!(std::fs::read_to_string(repos_path.join("1.txt"))?, "b");
+    assert!(reset::run(&repos_path, "@{2}", reset::ResetMode::Hard).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_amended);
+    assert!(reset::run(&repos_path, "master@{10}", reset::ResetMode::Hard).is_err());
+    assert!(new_branch::run(&repos_path, "dev@{1}", &None).is_err());
     VcsState::assert_validity(&repos_path);
-    assert!(merge::run(&repos_path, "dev").is_ok());
+
+    assert_eq!(
+        reflog::expire(&repos_path, &Some("HEAD".to_owned()))?,
+        "Expired 6 reflog entries"
+    );
+    assert_eq!(reflog::run(&repos_path, &None)?, "Reflog of HEAD is empty");
+
+    // a renamed branch does not replace the log of a deleted branch or of HEAD
+    assert!(new_branch::run(&repos_path, "old", &None).is_ok());
+    write(repos_path.join("1.txt"), "lost")?;
+    assert!(commit::run(&repos_path, "lost-work", RENAMES).is_ok());
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    assert!(branch::delete(&repos_path, "old", true).is_ok());
+    assert!(new_branch::run(&repos_path, "tmp", &None).is_ok());
+    assert!(branch::rename(&repos_path, Some("tmp"), "old").is_ok());
+    let output = reflog::run(&repos_path, &Some("old".to_owned()))?;
+    assert!(output.contains("commit: lost-work"));
+    assert!(output.contains("branch: Deleted branch old"));
+    assert!(output.starts_with(&format!(
+        "{} old@{{0}}: branch: Renamed branch tmp to old",
+        hash_amended.short_str()
+    )));
+    let head_log = reflog::run(&repos_path, &None)?;
+    assert!(branch::rename(&repos_path, Some("old"), "HEAD").is_err());
+    assert_eq!(reflog::run(&repos_path, &None)?, head_log);
     VcsState::assert_validity(&repos_path);
 
     remove_dir_all(&repos_path)?;
     Ok(())
 }
+
+#[test]
+fn test_undo_redo() -> Result<(), Error> {
+    let repos_str = "test_undo_redo";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "a")?;
+    assert!(init::run(&repos_path).is_ok());
+    assert!(op::undo(&repos_path).is_err());
+    write(repos_path.join("1.txt"), "b")?;
+    assert!(commit::run(&repos_path, "second", RENAMES).is_ok());
+    let hash_second = VcsState::load(&repos_path)?.head;
+    assert!(new_branch::run(&repos_path, "feature", &None).is_ok());
+    write(repos_path.join("2.txt"), "c")?;
+    assert!(commit::run(&repos_path, "feature change", RENAMES).is_ok());
+    let hash_feature = VcsState::load(&repos_path)?.head;
+    assert!(jump::run(&repos_path, &Some("master".to_owned()), &None).is_ok());
+    let options = MergeOptions {
+        fast_forward: FastForward::Never,
+        ..Default::default()
+    };
+    assert!(merge::run(&repos_path, "feature", &options).is_ok());
+    let hash_merge = VcsState::load(&repos_path)?.head;
+    // a failed command is not an operation
+    assert!(merge::run(&repos_path, "unknown", &options).is_err());
+    assert_eq!(op::log(&repos_path)?.lines().count(), 6);
+    assert!(op::log(&repos_path)?.starts_with("@ 5 "));
+    assert!(op::redo(&repos_path).is_err());
+
+    let output = op::undo(&repos_path)?;
+    assert!(output.starts_with("Undid operation: merge feature"));
+    assert!(output.ends_with(&format!(
+        "HEAD is at {} on branch master",
+        hash_second.short_str()
+    )));
+    assert!(!repos_path.join("2.txt").exists());
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert!(op::log(&repos_path)?.contains("\n@ 4 "));
+    assert!(op::redo(&repos_path)?.starts_with("Redid operation: merge feature"));
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_merge);
+    assert_eq!(std::fs::read_to_string(repos_path.join("2.txt"))?, "c");
+
+    assert!(op::undo(&repos_path).is_ok());
+    assert!(op::undo(&repos_path).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(
+        (state.head, state.branch_name.as_str()),
+        (hash_feature, "feature")
+    );
+    write(repos_path.join("2.txt"), "d")?;
+    assert!(op::undo(&repos_path).is_err());
+    assert!(restore::run(&repos_path, &[], &None, false).is_ok());
+    assert!(op::undo(&repos_path).is_ok());
+    assert!(op::undo(&repos_path).is_ok());
+    let state = VcsState::load(&repos_path)?;
+    assert_eq!(state.branches.len(), 1);
+    assert_eq!(state.head, hash_second);
+    assert!(reflog::run(&repos_path, &None)?
+        .contains("HEAD@{0}: undo: Undoing commit -m \"feature change\""));
+    VcsState::assert_validity(&repos_path);
+
+    // the undone commits are removed once the reflog is expired, the operations with them too
+    assert!(reflog::expire(&repos_path, &None).is_ok());
+    assert!(gc::run(&repos_path)?.starts_with("Removed 2 unreachable commits"));
+    assert!(op::redo(&repos_path)?.starts_with("Redid operation: new-branch feature"));
+    assert!(op::redo(&repos_path).is_err());
+    write(repos_path.join("3.txt"), "e")?;
+    assert!(commit::run(&repos_path, "new feature", RENAMES).is_ok());
+    assert!(op::log(&repos_path)?.starts_with("@ 3 "));
+    VcsState::assert_validity(&repos_path);
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_bisect() -> Result<(), Error> {
+    let repos_str = "test_bisect";
+    let repos_path = current_dir()?.join(repos_str);
+    assert!(!repos_path.try_exists()?, "{:?}", repos_path);
+
+    create_dir(&repos_path)?;
+    write(repos_path.join("1.txt"), "0")?;
+    assert!(init::run(&repos_path).is_ok());
+    let hash_initial = VcsState::load(&repos_path)?.head;
+    let mut hash_bug = hash_initial;
+    for i in 1..=8 {
+        write(repos_path.join("1.txt"), i.to_string())?;
+        if i == 5 {
+            write(repos_path.join("bug.txt"), "bug")?;
+        }
+        assert!(commit::run(&repos_path, &format!("change {i}"), RENAMES).is_ok());
+        if i == 5 {
+            hash_bug = VcsState::load(&repos_path)?.head;
+        }
+    }
+    let hash_last = VcsState::load(&repos_path)?.head;
+
+    assert!(bisect::good(&repos_path, &None).is_err());
+    assert_eq!(
+        bisect::start(&repos_path, &None, &[])?,
+        "Waiting for both good and bad commits"
+    );
+    assert!(bisect::start(&repos_path, &None, &[]).is_err());
+    assert_eq!(
+        bisect::bad(&repos_path, &None)?,
+        "Waiting for a good commit"
+    );
+    let mut output = bisect::good(&repos_path, &Some(hash_initial.to_string()))?;
+    assert!(output.starts_with("Bisecting: 3 revisions left to test after this (roughly 2 steps)"));
+    assert!(status::run(&repos_path, RENAMES)?
+        .contains("You are currently bisecting, started from branch master."));
+    let mut steps = 0;
+    while output.starts_with("Bisecting") {
+        steps += 1;
+        output = if repos_path.join("bug.txt").exists() {
+            bisect::bad(&repos_path, &None)?
+        } else {
+            bisect::good(&repos_path, &None)?
+        };
+    }
+    assert!(steps <= 3);
+    assert!(output.starts_with(&format!("{} is the first bad commit\nchange 5", hash_bug)));
+    assert!(bisect::reset(&repos_path)?.ends_with("on branch master"));
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_last);
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+    assert!(bisect::reset(&repos_path).is_err());
+
+    // "test" exits with 1 once bug.txt exists
+    assert!(bisect::start(
+        &repos_path,
+        &Some("HEAD".to_owned()),
+        &[hash_initial.to_string()]
+    )
+    .is_ok());
+    assert!(bisect::skip(&repos_path, &Some(hash_bug.to_string())).is_ok());
+    let command = ["test", "!", "-e", "bug.txt"].map(String::from);
+    let output = bisect::run(&repos_path, &command)?;
+    assert!(output.contains("There are only skipped commits left to test."));
+    assert!(output.contains(&format!("  {}\n", hash_bug)));
+    VcsState::assert_validity(&repos_path);
+    assert!(gc::run(&repos_path)?.starts_with("Removed 0 unreachable commits"));
+    assert!(bisect::reset(&repos_path).is_ok());
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_last);
+    assert!(op::undo(&repos_path).is_ok());
+    assert!(status::run(&repos_path, RENAMES)?.contains("You are currently bisecting"));
+    assert!(op::redo(&repos_path).is_ok());
+    assert!(!status::run(&repos_path, RENAMES)?.contains("You are currently bisecting"));
+    assert_eq!(VcsState::load(&repos_path)?.head, hash_last);
+
+    // files made by the command do not stop the search
+    assert!(bisect::start(
+        &repos_path,
+        &Some("HEAD".to_owned()),
+        &[hash_initial.to_string()]
+    )
+    .is_ok());
+    let command = [
+        "sh",
+        "-c",
+        "mkdir -p build/obj; touch out.log build/obj/1.o; test ! -e bug.txt",
+    ]
+    .map(String::from);
+    let output = bisect::run(&repos_path, &command)?;
+    assert!(output.starts_with(
+        "Removed files made by the command:\n  build/obj/1.o\n  out.log\n  build/obj/\n  build/\n"
+    ));
+    assert!(output.contains(&format!("{} is the first bad commit", hash_bug)));
+    assert!(!repos_path.join("out.log").exists());
+    assert!(!repos_path.join("build").exists());
+    assert!(bisect::reset(&repos_path).is_ok());
+    assert_eq!(
+        status::run(&repos_path, RENAMES)?,
+        "No changes to be committed"
+    );
+
+    remove_dir_all(&repos_path)?;
+    Ok(())
+}